@@ -0,0 +1,58 @@
+use image::{imageops::FilterType, RgbaImage};
+use ratatui::{layout::Rect, text::Text};
+
+pub const DEFAULT_RAMP: &str = " .:-=+*#%@";
+
+/// Renders frames as plain text, for terminals that can't show images at all.
+/// Each character cell gets one character from the ramp, picked by the
+/// brightness of that part of the frame.
+#[derive(Debug, Clone)]
+pub struct AsciiRenderer {
+    ramp: Vec<char>,
+    size: Option<(u16, u16)>,
+}
+
+impl AsciiRenderer {
+    /// `ramp` goes from darkest to brightest. If `size` isn't given, the
+    /// output is fitted to the area it's rendered into.
+    pub fn new(ramp: &str, size: Option<(u16, u16)>) -> Self {
+        Self {
+            ramp: ramp.chars().collect(),
+            size,
+        }
+    }
+
+    pub fn render(&self, frame: &RgbaImage, area: Rect) -> Text<'static> {
+        let (columns, rows) = self.size.unwrap_or_else(|| fit(frame, area));
+        if columns == 0 || rows == 0 || self.ramp.is_empty() {
+            return Text::default();
+        }
+
+        let small =
+            image::imageops::resize(frame, columns as u32, rows as u32, FilterType::Triangle);
+
+        let lines = small
+            .rows()
+            .map(|row| {
+                row.map(|pixel| {
+                    let [r, g, b, _] = pixel.0;
+                    let luminance = (299 * r as usize + 587 * g as usize + 114 * b as usize) / 1000;
+                    self.ramp[luminance * (self.ramp.len() - 1) / 255]
+                })
+                .collect::<String>()
+            })
+            .map(ratatui::text::Line::from)
+            .collect::<Vec<_>>();
+
+        Text::from(lines)
+    }
+}
+
+/// Fits the frame into the area, keeping its aspect ratio. Character cells
+/// are roughly twice as tall as they are wide, which we need to account for.
+fn fit(frame: &RgbaImage, area: Rect) -> (u16, u16) {
+    let (width, height) = (frame.width().max(1), frame.height().max(1));
+    let columns = (area.width as u32).min(area.height as u32 * width * 2 / height);
+    let rows = columns * height / width / 2;
+    (columns as u16, rows as u16)
+}
@@ -0,0 +1,114 @@
+use anyhow::{Context, Result};
+use image::DynamicImage;
+use ratatui::{buffer::Buffer, layout::Rect, widgets::Widget};
+use ratatui_image::{
+    picker::{Picker, ProtocolType},
+    protocol::Protocol,
+    FontSize, Image, Resize,
+};
+
+/// Turns frames into something the terminal can show. The rest of the app
+/// only goes through this, so another encoder (e.g. a faster sixel encoder)
+/// can be dropped in without touching it.
+///
+/// Backends start from what `Picker` found out about the terminal, since
+/// that's where the protocol and font size come from either way.
+pub trait ImageBackend {
+    /// An encoded frame, ready to draw as many times as needed.
+    type Frame;
+
+    fn new(picker: Picker) -> Self;
+
+    /// Encodes an image to show in `area`. If it's too big, it's cropped
+    /// with `crop`, and scaled down to fit otherwise.
+    fn encode(&mut self, image: DynamicImage, area: Rect, crop: bool) -> Result<Self::Frame>;
+
+    fn render(frame: &Self::Frame, area: Rect, buf: &mut Buffer);
+
+    /// The cells an encoded frame takes up.
+    fn frame_area(frame: &Self::Frame) -> Rect;
+
+    /// The protocol frames are encoded with, for showing the user and
+    /// looking up per-protocol settings.
+    fn protocol_name(&self) -> String;
+
+    /// Whether `next_protocol` would go back to the first protocol.
+    fn on_last_protocol(&self) -> bool;
+
+    fn next_protocol(&mut self);
+
+    /// Switches to a protocol by its lowercase name. Returns whether there
+    /// was one by that name.
+    fn select_protocol(&mut self, name: &str) -> bool {
+        let start = self.protocol_name();
+        loop {
+            if self.protocol_name().to_lowercase() == name {
+                return true;
+            }
+            self.next_protocol();
+            if self.protocol_name() == start {
+                return false;
+            }
+        }
+    }
+
+    /// Sets the font size to encode for. Frames are sized in cells, so a
+    /// smaller font makes them bigger.
+    fn set_font_size(&mut self, font_size: FontSize);
+}
+
+/// The backend to use. Other backends go behind a Cargo feature and
+/// replace this when it's enabled.
+pub type Backend = RatatuiImageBackend;
+
+/// Encodes frames with `ratatui_image`, which handles all the protocols we
+/// support.
+pub struct RatatuiImageBackend {
+    picker: Picker,
+}
+
+impl ImageBackend for RatatuiImageBackend {
+    type Frame = Protocol;
+
+    fn new(picker: Picker) -> Self {
+        Self { picker }
+    }
+
+    fn encode(&mut self, image: DynamicImage, area: Rect, crop: bool) -> Result<Protocol> {
+        let resize = if crop {
+            Resize::Crop(None)
+        } else {
+            Resize::Fit(None)
+        };
+        self.picker
+            .new_protocol(image, area, resize)
+            .context("Failed to encode frame")
+    }
+
+    fn render(frame: &Protocol, area: Rect, buf: &mut Buffer) {
+        Image::new(frame).render(area, buf);
+    }
+
+    fn frame_area(frame: &Protocol) -> Rect {
+        frame.area()
+    }
+
+    fn protocol_name(&self) -> String {
+        format!("{:?}", self.picker.protocol_type())
+    }
+
+    fn on_last_protocol(&self) -> bool {
+        matches!(self.picker.protocol_type().next(), ProtocolType::Halfblocks)
+    }
+
+    fn next_protocol(&mut self) {
+        let next = self.picker.protocol_type().next();
+        self.picker.set_protocol_type(next);
+    }
+
+    fn set_font_size(&mut self, font_size: FontSize) {
+        let protocol_type = self.picker.protocol_type();
+        self.picker = Picker::from_fontsize(font_size);
+        self.picker.set_protocol_type(protocol_type);
+    }
+}
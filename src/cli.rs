@@ -0,0 +1,718 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use ratatui::{style::Color, symbols::border};
+
+use crate::{
+    color_filter::ColorFilter,
+    config,
+    fit::{FitAxis, FitMode},
+    frame_dump::FrameDumpFormat,
+    gamma::Gamma,
+    levels::Levels,
+    margins::Margins,
+    menu::MenuDetection,
+    pixel_format::PixelFormat,
+    preset::ViewPreset,
+    zoom::ZoomStrategy,
+};
+
+/// Options passed on the command line.
+#[derive(Debug, Default)]
+pub struct Options {
+    /// A file to load key bindings from, see `KeyBindings` for the format.
+    pub keys: Option<PathBuf>,
+
+    /// Log keys that do nothing, to help with writing key bindings.
+    pub log_unknown_keys: bool,
+
+    /// Where the player's health lives in WASM memory. When set, the app
+    /// rings the terminal bell and flashes the border when the player dies.
+    /// Doom doesn't export this, so it has to come from the build's symbols.
+    pub death_alert: Option<u32>,
+
+    /// Where the current episode and map numbers live in WASM memory, for
+    /// showing the map name. Without an episode, maps are named like Doom
+    /// II's.
+    pub episode_address: Option<u32>,
+    pub map_address: Option<u32>,
+
+    /// The maximum number of pages WASM memory may grow to. Defaults to the
+    /// most WASM allows, 65536 pages or 4 GiB.
+    pub max_memory_pages: Option<u32>,
+
+    /// Don't render the game at all, only show stats and the log. The game
+    /// keeps running in the background.
+    pub stats_only: bool,
+
+    /// Start paused, showing the first frame, until a key is pressed.
+    pub start_paused: bool,
+
+    /// The characters used for ASCII rendering, from darkest to brightest.
+    pub ascii_ramp: Option<String>,
+
+    /// The size of ASCII renders in characters. Renders are fitted to the
+    /// terminal if this isn't set.
+    pub ascii_size: Option<(u16, u16)>,
+
+    /// A CPU core to pin the game to, for more consistent timing. Only
+    /// available with the `affinity` feature.
+    pub pin_cpu: Option<usize>,
+
+    /// How many ticks a second to run Doom at, instead of 35.
+    pub tick_rate: Option<f32>,
+
+    /// The seed handed to Doom builds that import `js_random_seed`.
+    pub seed: Option<u32>,
+
+    /// Patch WADs to hand to Doom builds that can load them, in order.
+    pub pwads: Vec<PathBuf>,
+
+    /// How the Doom build lays out its framebuffer.
+    pub pixel_format: PixelFormat,
+
+    /// A WAD to take the palettes for indexed framebuffers from, if no
+    /// PWAD has them.
+    pub palette_wad: Option<PathBuf>,
+
+    /// A directory to write every frame to, for making videos.
+    pub frame_dump: Option<PathBuf>,
+    pub frame_dump_format: FrameDumpFormat,
+    /// Stop dumping frames after this many.
+    pub frame_dump_limit: Option<u64>,
+
+    /// Print a histogram of the time between frames when quitting.
+    pub frame_histogram: bool,
+
+    /// How much memory to keep recent frames in for instant replay, in
+    /// bytes.
+    pub replay_mem: Option<u64>,
+    /// How much to scale replay frames down by each way, to fit more of
+    /// them.
+    pub replay_downscale: Option<u32>,
+
+    /// Quit after the game has run this many ticks, to bound scripted runs.
+    pub replay_max_ticks: Option<u32>,
+
+    /// Write the app's final screen to this file as text when quitting.
+    pub screen_text: Option<PathBuf>,
+
+    /// Run without a terminal and save the final screen, with styles, to
+    /// this file.
+    pub snapshot: Option<PathBuf>,
+
+    /// The screen size in snapshot mode.
+    pub snapshot_size: Option<(u16, u16)>,
+
+    /// How many ticks to run for in snapshot mode.
+    pub snapshot_ticks: Option<u32>,
+
+    /// Compare the final screen with the snapshot file instead of writing
+    /// it.
+    pub snapshot_check: bool,
+
+    /// The zoom to switch to when switching to a protocol, by lowercase
+    /// protocol name.
+    pub protocol_zoom: HashMap<String, u16>,
+
+    /// How zooming works for each protocol, by lowercase protocol name.
+    pub zoom_strategies: HashMap<String, ZoomStrategy>,
+
+    /// Re-render the screen at least this many times a second, even if Doom
+    /// hasn't drawn a new frame.
+    pub refresh_rate: Option<u32>,
+
+    /// The least time between two frames we encode and draw, however often
+    /// Doom draws.
+    pub min_draw_interval_ms: Option<u32>,
+
+    /// The color behind the image, visible where it doesn't fill the
+    /// screen. Defaults to the terminal's background.
+    pub background: Option<Color>,
+
+    /// The lines to draw the border with.
+    pub border: Option<border::Set>,
+
+    /// The terminal's font size in pixels, for terminals that report the
+    /// wrong one. Used instead of asking the terminal.
+    pub font_size: Option<(u16, u16)>,
+
+    /// Print what we know about the terminal's capabilities and exit.
+    pub caps: bool,
+
+    /// Ask the terminal which image protocols it supports, try each one,
+    /// print which ones work and exit.
+    pub list_protocols: bool,
+
+    /// Load Doom from this file instead of the bundled module.
+    pub wasm: Option<PathBuf>,
+
+    /// Print the functions the WASM module exports and exit.
+    pub list_exports: bool,
+
+    /// Check that the WASM module loads, runs and draws, then exit.
+    pub self_test: bool,
+
+    /// The most input events to handle between two game steps.
+    pub poll_budget: Option<usize>,
+
+    /// Wait for input until the next tick is due, instead of checking for it
+    /// every millisecond.
+    pub low_latency: bool,
+
+    /// Never sleep between game steps, keeping a CPU core busy.
+    pub no_sleep: bool,
+
+    /// The most ticks Doom may be behind before the rest are dropped.
+    pub max_ticks_behind: Option<u32>,
+
+    /// Measure how fast the terminal can draw, and skip frames it couldn't
+    /// show in time anyway.
+    pub adaptive_refresh: bool,
+
+    /// Log errors while drawing or reading Doom's output and keep going,
+    /// instead of crashing. A frame that fails to draw is skipped, and a log
+    /// line that can't be read shows the error instead.
+    pub safe: bool,
+
+    /// A `.cube` 3D LUT file to color grade the game with.
+    pub lut: Option<PathBuf>,
+
+    /// Gamma correction to start with, above 1 to brighten the image.
+    pub gamma: Option<f32>,
+
+    /// Brightness to start with, added to every channel as a fraction of
+    /// full white.
+    pub brightness: Option<f32>,
+
+    /// Contrast to start with, above 1 to stretch colors away from gray.
+    pub contrast: Option<f32>,
+
+    /// How long rendering a frame may take. When set, the image resolution
+    /// is lowered automatically to stay within it.
+    pub frame_budget_ms: Option<u32>,
+
+    /// Show the hash of each frame in the title.
+    pub show_hash: bool,
+
+    /// Show the last few events sent to Doom over the image.
+    pub show_doom_keys: bool,
+
+    /// A file to write the hash of every frame to.
+    pub record_hashes: Option<PathBuf>,
+
+    /// A file of frame hashes, from `--record-hashes`, to check this run
+    /// against.
+    pub verify_hashes: Option<PathBuf>,
+
+    /// How long a terminal draw may take before we decide the terminal is
+    /// gone and exit.
+    pub draw_timeout_ms: Option<u32>,
+
+    /// Write to the terminal from a separate thread, so slow terminal output
+    /// doesn't hold up the game.
+    pub render_thread: bool,
+
+    /// Color blindness compensation to start with.
+    pub color_filter: Option<ColorFilter>,
+
+    /// Darken every other row of the image, like an old CRT.
+    pub scanlines: bool,
+
+    /// Stretch the image to 4:3, the shape Doom was drawn for.
+    pub aspect_correction: bool,
+
+    /// A file to keep up to date with the game's status, as JSON.
+    pub status_file: Option<PathBuf>,
+
+    /// How often to write the status file, in milliseconds.
+    pub status_interval_ms: Option<u32>,
+
+    /// A named pipe or Unix socket to stream raw frames to.
+    pub frame_stream: Option<PathBuf>,
+
+    /// How long a key can go without any events before we assume the
+    /// terminal missed its release and release it ourselves. Zero turns
+    /// this off. Only on by default for terminals that can't report
+    /// releases.
+    pub key_release_ms: Option<u32>,
+
+    /// Don't pass key releases on to Doom, and rely on keys going stale
+    /// instead.
+    pub ignore_releases: bool,
+
+    /// The most turn key presses to forward per direction each tick. Extra
+    /// presses in the same tick are dropped.
+    pub turn_coalesce: Option<u32>,
+
+    /// How long to wait after setting up the terminal before the first
+    /// frame, for terminals that are slow to answer our queries.
+    pub startup_delay_ms: Option<u32>,
+
+    /// How to tell the game is in a menu, to render and step less often
+    /// while it is.
+    pub menu_throttle: Option<MenuDetection>,
+
+    /// How long a log line stays up, in seconds. 0 keeps it until the next
+    /// one.
+    pub log_retention_secs: Option<u32>,
+
+    /// Warn in the title when the FPS drops below this. 0 turns the warning
+    /// off.
+    pub low_fps: Option<u32>,
+
+    /// Show errors in the log line as red highlights rather than red text.
+    pub invert_errors: bool,
+
+    /// Where to keep save state slots.
+    pub state_dir: Option<PathBuf>,
+
+    /// Where to save recorded demos.
+    pub demo_dir: Option<PathBuf>,
+
+    /// The skill level to record demos with, from 1 to 5, since it isn't
+    /// exported either.
+    pub demo_skill: Option<u8>,
+
+    /// Start with the zoom picked automatically to fit the terminal.
+    pub auto_zoom: bool,
+
+    /// Let zooming in go past the point where the image stops fitting the
+    /// terminal or the font can't shrink any further.
+    pub no_clamp_zoom: bool,
+
+    /// Ask builds that can change resolution for one that matches the
+    /// terminal, whenever it's resized.
+    pub match_resolution: bool,
+
+    /// Which way auto zoom fits the image to the terminal.
+    pub fit_axis: FitAxis,
+
+    /// How the image is sized and placed: fitted, filling the terminal, or
+    /// at 1x in the corner or the middle.
+    pub fit_mode: FitMode,
+
+    /// Space around the image inside the border, in cells.
+    pub margins: Margins,
+
+    /// Sets of view settings to switch between with a key, in order.
+    pub view_presets: Vec<ViewPreset>,
+}
+
+impl Options {
+    pub fn parse() -> Result<Self> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    /// Parses the arguments. If they include `--config <path>`, the options
+    /// from that file are applied first, so the other arguments override
+    /// them.
+    pub fn parse_from(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut args: Vec<String> = args.into_iter().collect();
+        if let Some(index) = args.iter().position(|arg| arg == "--config") {
+            let path = args
+                .get(index + 1)
+                .context("Missing value for --config")?
+                .clone();
+            args.drain(index..index + 2);
+            let mut config_args = config::load_args(Path::new(&path))?;
+            config_args.append(&mut args);
+            args = config_args;
+        }
+
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--keys" => options.keys = Some(value(&mut args, &arg)?.into()),
+                "--log-unknown-keys" => options.log_unknown_keys = true,
+                "--death-alert" => options.death_alert = Some(number(&mut args, &arg)?),
+                "--episode-address" => options.episode_address = Some(number(&mut args, &arg)?),
+                "--map-address" => options.map_address = Some(number(&mut args, &arg)?),
+                "--max-memory-pages" => options.max_memory_pages = Some(number(&mut args, &arg)?),
+                "--stats-only" => options.stats_only = true,
+                "--ascii-ramp" => options.ascii_ramp = Some(value(&mut args, &arg)?),
+                "--ascii-size" => options.ascii_size = Some(size(&mut args, &arg)?),
+                "--pin-cpu" => options.pin_cpu = Some(number(&mut args, &arg)? as usize),
+                "--seed" => options.seed = Some(number(&mut args, &arg)?),
+                "--tick-rate" => match float(&mut args, &arg)? {
+                    hz if (1.0..=1000.0).contains(&hz) => options.tick_rate = Some(hz),
+                    _ => bail!("{arg} must be from 1 to 1000"),
+                },
+                "--pixel-format" => {
+                    options.pixel_format = PixelFormat::parse(&value(&mut args, &arg)?)?
+                }
+                "--pwad" => options.pwads.push(value(&mut args, &arg)?.into()),
+                "--palette-wad" => options.palette_wad = Some(value(&mut args, &arg)?.into()),
+                "--frame-dump" => options.frame_dump = Some(value(&mut args, &arg)?.into()),
+                "--frame-dump-format" => {
+                    options.frame_dump_format = FrameDumpFormat::parse(&value(&mut args, &arg)?)?
+                }
+                "--frame-dump-limit" => {
+                    options.frame_dump_limit = Some(number(&mut args, &arg)?.into())
+                }
+                "--frame-histogram" => options.frame_histogram = true,
+                "--replay-mem" => options.replay_mem = Some(bytes(&mut args, &arg)?),
+                "--replay-downscale" => match number(&mut args, &arg)? {
+                    divisor @ 1..=8 => options.replay_downscale = Some(divisor),
+                    _ => bail!("{arg} must be from 1 to 8"),
+                },
+                "--replay-max-ticks" => options.replay_max_ticks = Some(number(&mut args, &arg)?),
+                "--screen-text" => options.screen_text = Some(value(&mut args, &arg)?.into()),
+                "--snapshot" => options.snapshot = Some(value(&mut args, &arg)?.into()),
+                "--snapshot-size" => options.snapshot_size = Some(size(&mut args, &arg)?),
+                "--snapshot-ticks" => options.snapshot_ticks = Some(number(&mut args, &arg)?),
+                "--snapshot-check" => options.snapshot_check = true,
+                "--zoom-strategy" => {
+                    let (protocol, strategy) = protocol_pair(&mut args, &arg, "sixel=scale")?;
+                    options
+                        .zoom_strategies
+                        .insert(protocol, ZoomStrategy::parse(&strategy)?);
+                }
+                "--protocol-zoom" => {
+                    let (protocol, zoom) = protocol_zoom(&mut args, &arg)?;
+                    options.protocol_zoom.insert(protocol, zoom);
+                }
+                "--refresh-rate" => match number(&mut args, &arg)? {
+                    0 => bail!("{arg} must be at least 1"),
+                    hz => options.refresh_rate = Some(hz),
+                },
+                "--background" => options.background = Some(color(&mut args, &arg)?),
+                "--border" => options.border = Some(border_set(&mut args, &arg)?),
+                "--font-size" => match size(&mut args, &arg)? {
+                    (0, _) | (_, 0) => bail!("{arg} must be at least 1x1"),
+                    font_size => options.font_size = Some(font_size),
+                },
+                "--caps" => options.caps = true,
+                "--list-protocols" => options.list_protocols = true,
+                "--wasm" => options.wasm = Some(value(&mut args, &arg)?.into()),
+                "--list-exports" => options.list_exports = true,
+                "--self-test" => options.self_test = true,
+                "--poll-budget" => match number(&mut args, &arg)? {
+                    0 => bail!("{arg} must be at least 1"),
+                    budget => options.poll_budget = Some(budget as usize),
+                },
+                "--low-latency" => options.low_latency = true,
+                "--start-paused" => options.start_paused = true,
+                "--no-sleep" => options.no_sleep = true,
+                "--max-ticks-behind" => match number(&mut args, &arg)? {
+                    0 => bail!("{arg} must be at least 1"),
+                    ticks => options.max_ticks_behind = Some(ticks),
+                },
+                "--adaptive-refresh" => options.adaptive_refresh = true,
+                "--safe" => options.safe = true,
+                "--lut" => options.lut = Some(value(&mut args, &arg)?.into()),
+                "--gamma" => match float(&mut args, &arg)? {
+                    gamma if (Gamma::MIN..=Gamma::MAX).contains(&gamma) => {
+                        options.gamma = Some(gamma)
+                    }
+                    _ => bail!("{arg} must be from {} to {}", Gamma::MIN, Gamma::MAX),
+                },
+                "--brightness" => match float(&mut args, &arg)? {
+                    brightness
+                        if (Levels::MIN_BRIGHTNESS..=Levels::MAX_BRIGHTNESS)
+                            .contains(&brightness) =>
+                    {
+                        options.brightness = Some(brightness)
+                    }
+                    _ => bail!(
+                        "{arg} must be from {} to {}",
+                        Levels::MIN_BRIGHTNESS,
+                        Levels::MAX_BRIGHTNESS
+                    ),
+                },
+                "--contrast" => match float(&mut args, &arg)? {
+                    contrast
+                        if (Levels::MIN_CONTRAST..=Levels::MAX_CONTRAST).contains(&contrast) =>
+                    {
+                        options.contrast = Some(contrast)
+                    }
+                    _ => bail!(
+                        "{arg} must be from {} to {}",
+                        Levels::MIN_CONTRAST,
+                        Levels::MAX_CONTRAST
+                    ),
+                },
+                "--frame-budget-ms" => options.frame_budget_ms = Some(number(&mut args, &arg)?),
+                "--show-hash" => options.show_hash = true,
+                "--show-doom-keys" => options.show_doom_keys = true,
+                "--record-hashes" => options.record_hashes = Some(value(&mut args, &arg)?.into()),
+                "--verify-hashes" => options.verify_hashes = Some(value(&mut args, &arg)?.into()),
+                "--draw-timeout-ms" => match number(&mut args, &arg)? {
+                    0 => bail!("{arg} must be at least 1"),
+                    ms => options.draw_timeout_ms = Some(ms),
+                },
+                "--render-thread" => options.render_thread = true,
+                "--status-file" => options.status_file = Some(value(&mut args, &arg)?.into()),
+                "--status-interval-ms" => {
+                    options.status_interval_ms = Some(number(&mut args, &arg)?)
+                }
+                "--frame-stream" => options.frame_stream = Some(value(&mut args, &arg)?.into()),
+                "--ignore-releases" => options.ignore_releases = true,
+                "--key-release-ms" => options.key_release_ms = Some(number(&mut args, &arg)?),
+                "--min-draw-interval-ms" => {
+                    options.min_draw_interval_ms = Some(number(&mut args, &arg)?)
+                }
+                "--startup-delay-ms" => options.startup_delay_ms = Some(number(&mut args, &arg)?),
+                "--menu-throttle" => {
+                    options.menu_throttle = Some(MenuDetection::parse(&value(&mut args, &arg)?)?)
+                }
+                "--low-fps" => options.low_fps = Some(number(&mut args, &arg)?),
+                "--log-retention-secs" => {
+                    options.log_retention_secs = Some(number(&mut args, &arg)?)
+                }
+                "--invert-errors" => options.invert_errors = true,
+                "--auto-zoom" => options.auto_zoom = true,
+                "--no-clamp-zoom" => options.no_clamp_zoom = true,
+                "--match-resolution" => options.match_resolution = true,
+                "--fit-axis" => options.fit_axis = FitAxis::parse(&value(&mut args, &arg)?)?,
+                "--fit-mode" => options.fit_mode = FitMode::parse(&value(&mut args, &arg)?)?,
+                "--margins" => options.margins = Margins::parse(&value(&mut args, &arg)?)?,
+                "--view-preset" => options
+                    .view_presets
+                    .push(ViewPreset::parse(&value(&mut args, &arg)?)?),
+                "--state-dir" => options.state_dir = Some(value(&mut args, &arg)?.into()),
+                "--demo-dir" => options.demo_dir = Some(value(&mut args, &arg)?.into()),
+                "--demo-skill" => match number(&mut args, &arg)? {
+                    skill @ 1..=5 => options.demo_skill = Some(skill as u8),
+                    _ => bail!("{arg} must be from 1 to 5"),
+                },
+                "--turn-coalesce" => match number(&mut args, &arg)? {
+                    0 => bail!("{arg} must be at least 1"),
+                    max => options.turn_coalesce = Some(max),
+                },
+                "--color-filter" => {
+                    options.color_filter = Some(ColorFilter::parse(&value(&mut args, &arg)?)?)
+                }
+                "--scanlines" => options.scanlines = true,
+                "--aspect-correct" => options.aspect_correction = true,
+                "--config" => bail!("--config can only be given once, on the command line"),
+                _ => bail!("Unknown argument '{arg}'"),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String> {
+    args.next()
+        .with_context(|| format!("Missing value for {flag}"))
+}
+
+/// Reads a number, in decimal or `0x` hex, since addresses are usually
+/// written in hex.
+fn number(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<u32> {
+    let value = value(args, flag)?;
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+    .with_context(|| format!("Invalid number '{value}' for {flag}"))
+}
+
+fn float(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<f32> {
+    let value = value(args, flag)?;
+    value
+        .parse()
+        .with_context(|| format!("Invalid number '{value}' for {flag}"))
+}
+
+/// Reads an amount of memory, in bytes or with a `KB`, `MB` or `GB` suffix.
+fn bytes(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<u64> {
+    let value = value(args, flag)?;
+    let upper = value.to_ascii_uppercase();
+    let (number, unit) = [("GB", 1 << 30), ("MB", 1 << 20), ("KB", 1 << 10), ("B", 1)]
+        .into_iter()
+        .find_map(|(suffix, unit)| Some((upper.strip_suffix(suffix)?, unit)))
+        .unwrap_or((&upper, 1));
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(unit))
+        .with_context(|| format!("Invalid size '{value}' for {flag}, expected e.g. 64MB"))
+}
+
+/// Reads a size written as `<width>x<height>`.
+fn size(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<(u16, u16)> {
+    let value = value(args, flag)?;
+    value
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+        .with_context(|| format!("Invalid size '{value}' for {flag}, expected e.g. 80x25"))
+}
+
+pub const PROTOCOL_NAMES: &[&str] = &["halfblocks", "sixel", "kitty", "iterm2", "ascii"];
+
+/// Reads a `<protocol>=<value>` pair, with the protocol lowercased.
+fn protocol_pair(
+    args: &mut impl Iterator<Item = String>,
+    flag: &str,
+    example: &str,
+) -> Result<(String, String)> {
+    let value = value(args, flag)?;
+    let Some((protocol, rest)) = value.split_once('=') else {
+        bail!("Invalid value '{value}' for {flag}, expected e.g. {example}");
+    };
+
+    let protocol = protocol.to_ascii_lowercase();
+    if !PROTOCOL_NAMES.contains(&protocol.as_str()) {
+        bail!(
+            "Unknown protocol '{protocol}' for {flag}, expected one of {}",
+            PROTOCOL_NAMES.join(", ")
+        );
+    }
+    Ok((protocol, rest.to_string()))
+}
+
+/// Reads a `<protocol>=<zoom>` pair.
+fn protocol_zoom(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<(String, u16)> {
+    let (protocol, zoom) = protocol_pair(args, flag, "sixel=2")?;
+    match zoom.parse() {
+        Ok(zoom @ 1..) => Ok((protocol, zoom)),
+        _ => bail!("Invalid zoom '{zoom}' for {flag}, expected a number from 1 up"),
+    }
+}
+
+fn border_set(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<border::Set> {
+    let value = value(args, flag)?;
+    Ok(match value.as_str() {
+        "thick" => border::THICK,
+        "rounded" => border::ROUNDED,
+        "double" => border::DOUBLE,
+        "plain" => border::PLAIN,
+        _ => bail!("Unknown border '{value}' for {flag}, expected thick, rounded, double or plain"),
+    })
+}
+
+/// Reads a color, either by name (e.g. `black`, `darkgray`) or as `#rrggbb`.
+fn color(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<Color> {
+    let value = value(args, flag)?;
+    value
+        .parse()
+        .ok()
+        .with_context(|| format!("Invalid color '{value}' for {flag}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options> {
+        Options::parse_from(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn reads_memory_page_limits() {
+        assert_eq!(
+            parse(&["--max-memory-pages", "256"])
+                .unwrap()
+                .max_memory_pages,
+            Some(256)
+        );
+        assert_eq!(
+            parse(&["--max-memory-pages", "0x100"])
+                .unwrap()
+                .max_memory_pages,
+            Some(256)
+        );
+        assert!(parse(&["--max-memory-pages", "-1"]).is_err());
+        assert!(parse(&["--max-memory-pages", "4GB"]).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_and_incomplete_arguments() {
+        assert!(parse(&["--no-such-flag"]).is_err());
+        assert!(parse(&["--max-memory-pages"]).is_err());
+    }
+
+    #[test]
+    fn reads_death_alert_addresses() {
+        assert_eq!(
+            parse(&["--death-alert", "0x1f"]).unwrap().death_alert,
+            Some(0x1f)
+        );
+        assert_eq!(
+            parse(&["--death-alert", "31"]).unwrap().death_alert,
+            Some(31)
+        );
+        assert!(parse(&["--death-alert", "0xzz"]).is_err());
+    }
+
+    #[test]
+    fn reads_seeds() {
+        assert_eq!(parse(&["--seed", "42"]).unwrap().seed, Some(42));
+        assert!(parse(&["--seed", "-1"]).is_err());
+        assert!(parse(&["--seed"]).is_err());
+    }
+
+    #[test]
+    fn reads_protocol_zoom() {
+        let options = parse(&["--protocol-zoom", "Sixel=2", "--protocol-zoom", "kitty=3"]).unwrap();
+        assert_eq!(options.protocol_zoom.get("sixel"), Some(&2));
+        assert_eq!(options.protocol_zoom.get("kitty"), Some(&3));
+
+        for zoom in ["sixel", "sixel=0", "sixel=big", "teletype=2"] {
+            assert!(parse(&["--protocol-zoom", zoom]).is_err(), "{zoom}");
+        }
+    }
+
+    #[test]
+    fn collects_pwads() {
+        let options = parse(&["--pwad", "a.wad", "--pwad", "b.wad"]).unwrap();
+        assert_eq!(
+            options.pwads,
+            [PathBuf::from("a.wad"), PathBuf::from("b.wad")]
+        );
+    }
+
+    #[test]
+    fn command_line_overrides_config() {
+        let path = std::env::temp_dir().join(format!("doom-config-{}.toml", std::process::id()));
+        fs::write(&path, "seed = 1\nsafe = true\npwad = [\"a.wad\"]\n").unwrap();
+
+        let options = parse(&["--config", path.to_str().unwrap(), "--seed", "2"]);
+        fs::remove_file(&path).unwrap();
+        let options = options.unwrap();
+        assert_eq!(options.seed, Some(2));
+        assert!(options.safe);
+        assert_eq!(options.pwads, [PathBuf::from("a.wad")]);
+
+        assert!(parse(&["--config"]).is_err());
+    }
+
+    #[test]
+    fn reads_memory_sizes() {
+        assert_eq!(
+            parse(&["--replay-mem", "64MB"]).unwrap().replay_mem,
+            Some(64 << 20)
+        );
+        assert_eq!(
+            parse(&["--replay-mem", "12kb"]).unwrap().replay_mem,
+            Some(12 << 10)
+        );
+        assert_eq!(
+            parse(&["--replay-mem", "100"]).unwrap().replay_mem,
+            Some(100)
+        );
+        assert!(parse(&["--replay-mem", "lots"]).is_err());
+    }
+
+    #[test]
+    fn reads_font_sizes() {
+        assert_eq!(
+            parse(&["--font-size", "10x20"]).unwrap().font_size,
+            Some((10, 20))
+        );
+        for size in ["10", "10x", "0x20", "10x0", "axb"] {
+            assert!(parse(&["--font-size", size]).is_err(), "{size}");
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::time::{Duration, Instant};
+
+/// The time reported to Doom. It normally follows real time, but can be
+/// sped up, e.g. for turbo mode, or scaled, to change the tick rate.
+#[derive(Debug, Clone)]
+pub struct GameClock {
+    // Game time as of `since`, after which it advances at `speed` times
+    // real time
+    base: Duration,
+    since: Instant,
+    speed: u32,
+    // Applied on top of `speed`
+    scale: f64,
+    // Game time as of when Doom last caught up with the clock
+    mark: Duration,
+}
+
+impl Default for GameClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GameClock {
+    pub fn new() -> Self {
+        Self {
+            base: Duration::ZERO,
+            since: Instant::now(),
+            speed: 1,
+            scale: 1.0,
+            mark: Duration::ZERO,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.base + self.since.elapsed().mul_f64(self.rate())
+    }
+
+    /// How fast game time passes compared to real time.
+    fn rate(&self) -> f64 {
+        self.speed as f64 * self.scale
+    }
+
+    /// How long until the clock reaches `game_time`, in real time. Never
+    /// happens while the clock is stopped.
+    pub fn real_time_until(&self, game_time: Duration) -> Option<Duration> {
+        (self.speed > 0).then(|| {
+            game_time
+                .saturating_sub(self.elapsed())
+                .div_f64(self.rate())
+        })
+    }
+
+    /// Moves the clock forward, e.g. to step through a paused game.
+    pub fn advance(&mut self, by: Duration) {
+        self.base += by;
+    }
+
+    /// Jumps to the given game time, e.g. when loading a save state.
+    pub fn set_elapsed(&mut self, elapsed: Duration) {
+        self.base = elapsed;
+        self.since = Instant::now();
+        self.mark = elapsed;
+    }
+
+    /// Remembers the current game time as the point Doom has caught up to.
+    pub fn mark(&mut self) {
+        self.mark = self.elapsed();
+    }
+
+    /// If the clock is more than `max` past the mark, sets it back to `max`
+    /// past it, so Doom has no more than that to catch up on. Returns how
+    /// much game time was dropped.
+    pub fn limit_lag(&mut self, max: Duration) -> Duration {
+        let behind = self.elapsed().saturating_sub(self.mark);
+        if behind <= max {
+            return Duration::ZERO;
+        }
+        self.set_elapsed(self.mark + max);
+        behind - max
+    }
+
+    /// Leaves out time that has already passed, as if the clock had been
+    /// stopped for it.
+    pub fn skip(&mut self, real_time: Duration) {
+        self.since = self.since.checked_add(real_time).unwrap_or(self.since);
+    }
+
+    pub fn set_speed(&mut self, speed: u32) {
+        self.base = self.elapsed();
+        self.since = Instant::now();
+        self.speed = speed;
+    }
+
+    /// Makes the clock run `scale` times as fast as real time, on top of
+    /// the speed, e.g. to run Doom's 35 Hz tick loop at another rate.
+    pub fn set_scale(&mut self, scale: f64) {
+        self.base = self.elapsed();
+        self.since = Instant::now();
+        self.scale = scale;
+    }
+}
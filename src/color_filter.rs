@@ -0,0 +1,97 @@
+use anyhow::{bail, Result};
+use image::RgbaImage;
+
+type Matrix = [[f32; 3]; 3];
+
+// Between linear RGB and the LMS cone response space
+const RGB_TO_LMS: Matrix = [
+    [17.8824, 43.5161, 4.11935],
+    [3.45565, 27.1554, 3.86714],
+    [0.0299566, 0.184309, 1.46709],
+];
+const LMS_TO_RGB: Matrix = [
+    [0.080_944_45, -0.130_504_41, 0.116_721_07],
+    [-0.010_248_53, 0.054_019_33, -0.113_614_71],
+    [-0.000_365_3, -0.004_121_615, 0.693_511_4],
+];
+
+// Moves what's lost to the missing cone into the channels that can still
+// be told apart
+const ERROR_SHIFT: Matrix = [[0.0, 0.0, 0.0], [0.7, 1.0, 0.0], [0.7, 0.0, 1.0]];
+
+/// Color compensation for color blindness, so things Doom tells apart by
+/// color alone, like keycards, stay distinguishable. This is daltonization:
+/// simulate how the frame looks with the color blindness, then shift the
+/// difference into colors that can still be seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorFilter {
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl ColorFilter {
+    pub const ALL: [Self; 3] = [Self::Protanopia, Self::Deuteranopia, Self::Tritanopia];
+
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "protanopia" => Ok(Self::Protanopia),
+            "deuteranopia" => Ok(Self::Deuteranopia),
+            "tritanopia" => Ok(Self::Tritanopia),
+            _ => bail!(
+                "Unknown color filter '{value}', expected protanopia, deuteranopia or tritanopia"
+            ),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Protanopia => "Protanopia",
+            Self::Deuteranopia => "Deuteranopia",
+            Self::Tritanopia => "Tritanopia",
+        }
+    }
+
+    pub fn apply(self, image: &mut RgbaImage) {
+        let matrix = self.matrix();
+        for pixel in image.pixels_mut() {
+            let color = [pixel[0], pixel[1], pixel[2]].map(f32::from);
+            let filtered = multiply_vector(&matrix, color);
+            for (channel, value) in pixel.0.iter_mut().zip(filtered) {
+                *channel = value.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+
+    // The whole transform, collapsed into one matrix: the original color,
+    // plus the shifted difference between it and the simulated one
+    fn matrix(self) -> Matrix {
+        let simulation = match self {
+            Self::Protanopia => [[0.0, 2.02344, -2.52581], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            Self::Deuteranopia => [[1.0, 0.0, 0.0], [0.494207, 0.0, 1.24827], [0.0, 0.0, 1.0]],
+            Self::Tritanopia => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [-0.395913, 0.801109, 0.0]],
+        };
+        let simulated = multiply(&LMS_TO_RGB, &multiply(&simulation, &RGB_TO_LMS));
+
+        let mut lost = [[0.0; 3]; 3];
+        for (i, row) in lost.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = if i == j { 1.0 } else { 0.0 } - simulated[i][j];
+            }
+        }
+
+        let mut result = multiply(&ERROR_SHIFT, &lost);
+        for (i, row) in result.iter_mut().enumerate() {
+            row[i] += 1.0;
+        }
+        result
+    }
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    std::array::from_fn(|i| std::array::from_fn(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+fn multiply_vector(matrix: &Matrix, vector: [f32; 3]) -> [f32; 3] {
+    std::array::from_fn(|i| (0..3).map(|k| matrix[i][k] * vector[k]).sum())
+}
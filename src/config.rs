@@ -0,0 +1,229 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+
+/// Reads a config file, and turns it into the command line arguments it
+/// stands for, so it goes through the same parsing (and checks) as the
+/// command line itself.
+///
+/// The file is TOML, with one `key = value` pair per line. Keys are the
+/// command line flags without the leading `--`, e.g. `max-memory-pages`;
+/// underscores work too. Values are:
+///
+/// - `true` for flags without a value, e.g. `safe = true`. `false` leaves the
+///   flag out.
+/// - A number or a string, e.g. `seed = 42` or `keys = "keys.txt"`.
+/// - An array, for flags that can be given more than once, e.g.
+///   `pwad = ["a.wad", "b.wad"]`.
+///
+/// Tables aren't supported, since there's nothing to group.
+///
+/// ```toml
+/// # Big and fast
+/// auto-zoom = true
+/// protocol-zoom = ["sixel=2", "kitty=2"]
+/// background = "#202020"
+/// ```
+pub fn load_args(path: &Path) -> Result<Vec<String>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config from {}", path.display()))?;
+    parse_args(&contents).with_context(|| format!("Invalid config in {}", path.display()))
+}
+
+pub fn parse_args(contents: &str) -> Result<Vec<String>> {
+    let mut args = vec![];
+
+    for (index, line) in contents.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') {
+            bail!("Line {}: tables aren't supported", index + 1);
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            bail!("Line {}: expected `key = value`", index + 1);
+        };
+        let flag = format!("--{}", key.trim().replace('_', "-"));
+
+        match value.trim() {
+            "true" => args.push(flag),
+            "false" => {}
+            value => {
+                for value in parse_values(value).with_context(|| format!("Line {}", index + 1))? {
+                    args.push(flag.clone());
+                    args.push(value);
+                }
+            }
+        }
+    }
+
+    Ok(args)
+}
+
+/// Cuts a `#` comment off a line, unless it's inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, ch) in line.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Parses a single value, or each value in an array.
+fn parse_values(value: &str) -> Result<Vec<String>> {
+    let Some(items) = value.strip_prefix('[') else {
+        return Ok(vec![parse_value(value)?]);
+    };
+    let Some(items) = items.strip_suffix(']') else {
+        bail!("Unterminated array '{value}'");
+    };
+
+    split_items(items)?
+        .into_iter()
+        .map(|item| parse_value(item.trim()))
+        .collect()
+}
+
+/// Splits an array's contents on commas outside of strings, allowing a
+/// trailing comma.
+fn split_items(items: &str) -> Result<Vec<&str>> {
+    let mut result = vec![];
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (index, ch) in items.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                result.push(&items[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    if in_string {
+        bail!("Unterminated string in '[{items}]'");
+    }
+    if !items[start..].trim().is_empty() {
+        result.push(&items[start..]);
+    }
+    Ok(result)
+}
+
+fn parse_value(value: &str) -> Result<String> {
+    let Some(quoted) = value.strip_prefix('"') else {
+        // Bare values are numbers, which the flags parse themselves, e.g.
+        // `0x1f`, `64MB` or `-0.2`
+        let number_char = |ch: char| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '+' | '.');
+        let valid = value.chars().all(|ch| ch.is_ascii_alphanumeric())
+            || (value.starts_with(|ch: char| ch.is_ascii_digit() || matches!(ch, '-' | '+' | '.'))
+                && value.chars().all(number_char));
+        if value.is_empty() || !valid {
+            bail!("Invalid value '{value}', expected a number, a string, `true` or `false`");
+        }
+        return Ok(value.to_string());
+    };
+    let Some(quoted) = quoted.strip_suffix('"') else {
+        bail!("Unterminated string {value}");
+    };
+
+    let mut result = String::new();
+    let mut chars = quoted.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            result.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('"') => result.push('"'),
+            Some('\\') => result.push('\\'),
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            other => bail!(
+                "Unsupported escape '\\{}' in {value}",
+                other.map(String::from).unwrap_or_default()
+            ),
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_values_to_flags() {
+        let args = parse_args(
+            "# Big and fast\n\
+             auto-zoom = true\n\
+             safe = false\n\
+             max_memory_pages = 256\n\
+             keys = \"my keys.txt\" # not part of the path\n\
+             pwad = [\"a.wad\", \"b,c.wad\",]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            args,
+            [
+                "--auto-zoom",
+                "--max-memory-pages",
+                "256",
+                "--keys",
+                "my keys.txt",
+                "--pwad",
+                "a.wad",
+                "--pwad",
+                "b,c.wad",
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_bare_floats() {
+        let args = parse_args("gamma = 1.2\nbrightness = -0.2\ntick-rate = 6.05e1\n").unwrap();
+        assert_eq!(
+            args,
+            [
+                "--gamma",
+                "1.2",
+                "--brightness",
+                "-0.2",
+                "--tick-rate",
+                "6.05e1"
+            ]
+        );
+    }
+
+    #[test]
+    fn unescapes_strings() {
+        let args = parse_args(r##"background = "#20\"20\\20""##).unwrap();
+        assert_eq!(args, ["--background", r#"#20"20\20"#]);
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        for contents in [
+            "[view]",
+            "auto-zoom",
+            "keys = \"keys.txt",
+            "pwad = [\"a.wad\"",
+            "pwad = [\"a.wad]",
+            "keys = keys.txt",
+            "keys = \"\\x\"",
+        ] {
+            assert!(parse_args(contents).is_err(), "{contents}");
+        }
+    }
+}
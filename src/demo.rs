@@ -0,0 +1,181 @@
+use std::{collections::HashSet, fs, path::Path};
+
+use anyhow::{Context, Result};
+
+// Doom key codes, as sent to `add_browser_event`
+const KEY_RIGHT: i32 = 0xae;
+const KEY_LEFT: i32 = 0xac;
+const KEY_UP: i32 = 0xad;
+const KEY_DOWN: i32 = 0xaf;
+const KEY_FIRE: i32 = 0x80 + 0x1d;
+const KEY_STRAFE: i32 = 0x80 + 0x38;
+// What `c` maps to, plus vanilla's right shift
+const KEYS_RUN: [i32; 2] = [16, 0x80 + 0x36];
+const KEY_USE: i32 = b' ' as i32;
+const KEY_STRAFE_LEFT: i32 = b',' as i32;
+const KEY_STRAFE_RIGHT: i32 = b'.' as i32;
+
+// Movement speeds from vanilla's G_BuildTiccmd, walking and running
+const FORWARD_MOVE: [i32; 2] = [0x19, 0x32];
+const SIDE_MOVE: [i32; 2] = [0x18, 0x28];
+// Turning starts slow, for the first few tics a turn key is held
+const ANGLE_TURN: [i32; 3] = [640, 1280, 320];
+const SLOW_TURN_TICS: u32 = 6;
+const MAX_MOVE: i32 = FORWARD_MOVE[1];
+
+const BT_ATTACK: u8 = 1;
+const BT_USE: u8 = 2;
+const BT_CHANGE: u8 = 4;
+const BT_WEAPON_SHIFT: u8 = 3;
+
+const DEMO_VERSION: u8 = 109;
+const DEMO_END: u8 = 0x80;
+
+/// Records what the player does as a vanilla Doom 1.9 `.lmp` demo, which
+/// other source ports can play back.
+///
+/// Doom only gives us key events, so this rebuilds the tic commands the way
+/// vanilla does from its default key bindings. Demos also have to start at
+/// the start of a level to play back correctly, since they don't include
+/// the game state.
+#[derive(Debug, Clone)]
+pub struct DemoRecorder {
+    skill: u8,
+    episode: u8,
+    map: u8,
+    held_keys: HashSet<i32>,
+    turn_held: u32,
+    tics: Vec<[u8; 4]>,
+}
+
+impl DemoRecorder {
+    pub fn new(skill: u8, episode: u8, map: u8) -> Self {
+        Self {
+            skill,
+            episode,
+            map,
+            held_keys: HashSet::new(),
+            turn_held: 0,
+            tics: vec![],
+        }
+    }
+
+    /// Starts the demo over when a level starts, since tics from before it
+    /// would throw the whole demo out of sync on playback.
+    pub fn start_level(&mut self, episode: u8, map: u8) {
+        self.episode = episode;
+        self.map = map;
+        self.turn_held = 0;
+        self.tics.clear();
+    }
+
+    pub fn tics(&self) -> usize {
+        self.tics.len()
+    }
+
+    /// Tracks a key event sent to Doom, with the same arguments as
+    /// `add_browser_event`.
+    pub fn key_event(&mut self, kind: i32, code: i32) {
+        match kind {
+            0 => {
+                self.held_keys.insert(code);
+            }
+            1 => {
+                self.held_keys.remove(&code);
+            }
+            _ => {}
+        }
+    }
+
+    /// Records one tic, from the keys held right now.
+    pub fn record_tic(&mut self) {
+        let held = |code| self.held_keys.contains(&code);
+        let speed = KEYS_RUN.into_iter().any(held) as usize;
+
+        if held(KEY_LEFT) || held(KEY_RIGHT) {
+            self.turn_held += 1;
+        } else {
+            self.turn_held = 0;
+        }
+        let turn_speed = if self.turn_held < SLOW_TURN_TICS {
+            2
+        } else {
+            speed
+        };
+
+        let mut forward = 0;
+        let mut side = 0;
+        let mut angle_turn = 0;
+        let mut buttons = 0;
+
+        if held(KEY_STRAFE) {
+            if held(KEY_RIGHT) {
+                side += SIDE_MOVE[speed];
+            }
+            if held(KEY_LEFT) {
+                side -= SIDE_MOVE[speed];
+            }
+        } else {
+            if held(KEY_RIGHT) {
+                angle_turn -= ANGLE_TURN[turn_speed];
+            }
+            if held(KEY_LEFT) {
+                angle_turn += ANGLE_TURN[turn_speed];
+            }
+        }
+
+        if held(KEY_UP) {
+            forward += FORWARD_MOVE[speed];
+        }
+        if held(KEY_DOWN) {
+            forward -= FORWARD_MOVE[speed];
+        }
+        if held(KEY_STRAFE_RIGHT) {
+            side += SIDE_MOVE[speed];
+        }
+        if held(KEY_STRAFE_LEFT) {
+            side -= SIDE_MOVE[speed];
+        }
+
+        if held(KEY_FIRE) {
+            buttons |= BT_ATTACK;
+        }
+        if held(KEY_USE) {
+            buttons |= BT_USE;
+        }
+        if let Some(weapon) = (0..7u8).find(|weapon| held((b'1' + weapon) as i32)) {
+            buttons |= BT_CHANGE | (weapon << BT_WEAPON_SHIFT);
+        }
+
+        // Demos only keep the high byte of the turn, rounded
+        let angle_turn = ((angle_turn + 128) >> 8) as u8;
+        self.tics.push([
+            forward.clamp(-MAX_MOVE, MAX_MOVE) as i8 as u8,
+            side.clamp(-MAX_MOVE, MAX_MOVE) as i8 as u8,
+            angle_turn,
+            buttons,
+        ]);
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut data = vec![
+            DEMO_VERSION,
+            self.skill,
+            self.episode,
+            self.map,
+            0, // deathmatch
+            0, // respawn
+            0, // fast
+            0, // no monsters
+            0, // console player
+            1, // players in game
+            0,
+            0,
+            0,
+        ];
+        data.extend(self.tics.iter().flatten());
+        data.push(DEMO_END);
+
+        fs::write(path, data).with_context(|| format!("Failed to write demo to {}", path.display()))
+    }
+}
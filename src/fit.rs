@@ -0,0 +1,89 @@
+use anyhow::{bail, Result};
+
+/// Which way auto zoom fits the image to the terminal. Fitting one axis
+/// gives a bigger image on very wide or very tall terminals, at the cost of
+/// cropping the other one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FitAxis {
+    /// The whole image fits, however much space that leaves around it.
+    #[default]
+    Both,
+    /// The image fills the width, and is cropped at the bottom if it's too
+    /// tall.
+    Width,
+    /// The image fills the height, and is cropped on the right if it's too
+    /// wide.
+    Height,
+}
+
+impl FitAxis {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "both" => Ok(Self::Both),
+            "width" => Ok(Self::Width),
+            "height" => Ok(Self::Height),
+            _ => bail!("Unknown fit axis '{value}', expected both, width or height"),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Both => "both",
+            Self::Width => "width",
+            Self::Height => "height",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Self::Both => Self::Width,
+            Self::Width => Self::Height,
+            Self::Height => Self::Both,
+        }
+    }
+}
+
+/// How the image is sized and placed in the space it has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FitMode {
+    /// At the zoom the user picked, or auto zoom with the fit axis.
+    #[default]
+    Fit,
+    /// Zoomed in until it covers all the space, cropping whatever doesn't
+    /// fit.
+    Fill,
+    /// At 1x in the top left corner, cropped if it's too big.
+    Actual,
+    /// At 1x in the middle of the space, cropped if it's too big.
+    Center,
+}
+
+impl FitMode {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "fit" => Ok(Self::Fit),
+            "fill" => Ok(Self::Fill),
+            "actual" => Ok(Self::Actual),
+            "center" => Ok(Self::Center),
+            _ => bail!("Unknown fit mode '{value}', expected fit, fill, actual or center"),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Fit => "fit",
+            Self::Fill => "fill",
+            Self::Actual => "actual",
+            Self::Center => "center",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Self::Fit => Self::Fill,
+            Self::Fill => Self::Actual,
+            Self::Actual => Self::Center,
+            Self::Center => Self::Fit,
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    ops::Deref,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use image::{ImageBuffer, Rgba};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FrameDumpFormat {
+    #[default]
+    Png,
+    // Much faster to write than PNG, at the cost of disk space
+    Ppm,
+}
+
+impl FrameDumpFormat {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "png" => Ok(Self::Png),
+            "ppm" => Ok(Self::Ppm),
+            _ => bail!("Unknown frame dump format '{value}', expected png or ppm"),
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Ppm => "ppm",
+        }
+    }
+}
+
+/// Writes every frame Doom draws to a directory as a numbered image, at full
+/// resolution, for making videos.
+///
+/// Doom doesn't draw at a steady rate, so `timestamps.csv` next to the frames
+/// says when in game time each one was drawn, for putting a video together
+/// with the right timing.
+pub struct FrameDump {
+    dir: PathBuf,
+    format: FrameDumpFormat,
+    limit: Option<u64>,
+    written: u64,
+    timestamps: BufWriter<File>,
+}
+
+impl FrameDump {
+    pub fn new(dir: PathBuf, format: FrameDumpFormat, limit: Option<u64>) -> Result<Self> {
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create frame dump directory {}", dir.display()))?;
+
+        let timestamps_path = dir.join("timestamps.csv");
+        let mut timestamps = File::create(&timestamps_path)
+            .map(BufWriter::new)
+            .with_context(|| format!("Failed to create {}", timestamps_path.display()))?;
+        writeln!(timestamps, "frame,milliseconds,tick")
+            .and_then(|_| timestamps.flush())
+            .with_context(|| format!("Failed to write to {}", timestamps_path.display()))?;
+
+        Ok(Self {
+            dir,
+            format,
+            limit,
+            written: 0,
+            timestamps,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.limit.is_some_and(|limit| self.written >= limit)
+    }
+
+    /// Writes the next frame, drawn at `game_time` during `tick`, unless
+    /// we've hit the frame limit already.
+    pub fn write<C: Deref<Target = [u8]>>(
+        &mut self,
+        frame: &ImageBuffer<Rgba<u8>, C>,
+        game_time: Duration,
+        tick: u128,
+    ) -> Result<()> {
+        if self.is_full() {
+            return Ok(());
+        }
+
+        let path = self.dir.join(format!(
+            "frame{:06}.{}",
+            self.written,
+            self.format.extension()
+        ));
+
+        match self.format {
+            FrameDumpFormat::Png => frame.save(&path).map_err(anyhow::Error::from),
+            FrameDumpFormat::Ppm => write_ppm(frame, &path),
+        }
+        .with_context(|| format!("Failed to write frame to {}", path.display()))?;
+
+        // Flushed every frame, so it matches the frames on disk however the
+        // app exits
+        writeln!(
+            self.timestamps,
+            "{},{},{tick}",
+            self.written,
+            game_time.as_millis()
+        )
+        .and_then(|_| self.timestamps.flush())
+        .context("Failed to write frame timestamp")?;
+
+        self.written += 1;
+        Ok(())
+    }
+}
+
+fn write_ppm<C: Deref<Target = [u8]>>(frame: &ImageBuffer<Rgba<u8>, C>, path: &Path) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write!(file, "P6\n{} {}\n255\n", frame.width(), frame.height())?;
+    for pixel in frame.pixels() {
+        file.write_all(&pixel.0[..3])?;
+    }
+    file.flush()?;
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+
+/// Hashes a framebuffer with 64-bit FNV-1a. It's not the fastest hash
+/// around, but it's simple and gives the same results on every build, which
+/// is what matters when comparing runs.
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Records the hash of every frame to a file and/or checks them against a
+/// previously recorded run, to verify that runs are reproducible. Hash files
+/// have one frame hash per line, in hex.
+#[derive(Default)]
+pub struct FrameHashes {
+    record: Option<BufWriter<File>>,
+    expected: Option<Vec<u64>>,
+    frames: u64,
+    last: Option<u64>,
+    divergence: Option<Divergence>,
+}
+
+/// The first frame that didn't match the recorded run.
+#[derive(Debug, Clone, Copy)]
+pub struct Divergence {
+    pub frame: u64,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+impl FrameHashes {
+    pub fn new(record: Option<&Path>, verify: Option<&Path>) -> Result<Self> {
+        let record = record
+            .map(|path| {
+                File::create(path)
+                    .map(BufWriter::new)
+                    .with_context(|| format!("Failed to create hash file {}", path.display()))
+            })
+            .transpose()?;
+        let expected = verify.map(load).transpose()?;
+
+        Ok(Self {
+            record,
+            expected,
+            ..Default::default()
+        })
+    }
+
+    pub fn last(&self) -> Option<u64> {
+        self.last
+    }
+
+    /// Adds the hash of the next frame. Returns the divergence if this is
+    /// the first frame that doesn't match the recorded run.
+    pub fn add(&mut self, hash: u64) -> Result<Option<Divergence>> {
+        if let Some(record) = &mut self.record {
+            writeln!(record, "{hash:016x}").context("Failed to write frame hash")?;
+        }
+
+        let frame = self.frames;
+        self.frames += 1;
+        self.last = Some(hash);
+
+        let Some(expected) = &self.expected else {
+            return Ok(None);
+        };
+        // Frames past the end of the recorded run can't be checked
+        let Some(&expected) = expected.get(frame as usize) else {
+            return Ok(None);
+        };
+        if self.divergence.is_some() || expected == hash {
+            return Ok(None);
+        }
+
+        self.divergence = Some(Divergence {
+            frame,
+            expected,
+            actual: hash,
+        });
+        Ok(self.divergence)
+    }
+
+    /// Flushes the recorded hashes, and sums up the verification, if any.
+    pub fn finish(&mut self) -> Result<Option<String>> {
+        if let Some(record) = &mut self.record {
+            record.flush().context("Failed to write frame hashes")?;
+        }
+
+        Ok(self
+            .expected
+            .as_ref()
+            .map(|expected| match self.divergence {
+                Some(divergence) => divergence.to_string(),
+                None => format!(
+                    "Frame hashes matched for {} frames ({} recorded)",
+                    self.frames.min(expected.len() as u64),
+                    expected.len()
+                ),
+            }))
+    }
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Frame {} diverged: expected hash {:016x}, found {:016x}",
+            self.frame, self.expected, self.actual
+        )
+    }
+}
+
+fn load(path: &Path) -> Result<Vec<u64>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read hash file {}", path.display()))?;
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .enumerate()
+        .map(|(index, line)| {
+            u64::from_str_radix(line, 16).with_context(|| {
+                format!(
+                    "{}: invalid hash '{line}' for frame {index}",
+                    path.display()
+                )
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+
+const ONE_SECOND: Duration = Duration::from_secs(1);
+
+// Enough for a few seconds at Doom's 35 FPS
+const FRAME_TIME_HISTORY: usize = 128;
+
+// The upper end of each histogram bucket, in milliseconds. Doom's frames
+// are about 29ms apart at full speed. Anything slower than the last one
+// goes into an extra bucket.
+const HISTOGRAM_BUCKETS_MS: [u64; 9] = [5, 10, 20, 30, 40, 50, 75, 100, 200];
+const HISTOGRAM_WIDTH: u64 = 40;
+
+/// Counts the frames we show, for the FPS display and anything else that
+/// wants to know how well we're doing.
+#[derive(Debug, Clone)]
+pub struct FrameStats {
+    last_second: Instant,
+    frames_since_last_second: u16,
+    fps: u16,
+    total_frames: u64,
+    last_frame: Option<Instant>,
+    // The time between each of the most recent frames, oldest first
+    frame_times: VecDeque<Duration>,
+    // How many frame times fell into each bucket, over the whole session
+    histogram: [u64; HISTOGRAM_BUCKETS_MS.len() + 1],
+}
+
+impl Default for FrameStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        Self {
+            last_second: Instant::now(),
+            frames_since_last_second: 0,
+            fps: 0,
+            total_frames: 0,
+            last_frame: None,
+            frame_times: VecDeque::with_capacity(FRAME_TIME_HISTORY),
+            histogram: [0; HISTOGRAM_BUCKETS_MS.len() + 1],
+        }
+    }
+
+    /// The number of frames shown during the last full second.
+    pub fn fps(&self) -> u16 {
+        self.fps
+    }
+
+    pub fn total_frames(&self) -> u64 {
+        self.total_frames
+    }
+
+    /// The time between each of the most recent frames, oldest first.
+    pub fn frame_times(&self) -> impl ExactSizeIterator<Item = Duration> + '_ {
+        self.frame_times.iter().copied()
+    }
+
+    /// A text histogram of the time between frames over the whole session,
+    /// one bucket per line, to show how steady the frame rate was.
+    pub fn histogram(&self) -> String {
+        let most = self.histogram.iter().copied().max().unwrap_or(0).max(1);
+        let mut result = String::from("Time between frames:\n");
+        for (index, &count) in self.histogram.iter().enumerate() {
+            let range = match index {
+                0 => format!("<{}ms", HISTOGRAM_BUCKETS_MS[0]),
+                _ if index == HISTOGRAM_BUCKETS_MS.len() => {
+                    format!(">={}ms", HISTOGRAM_BUCKETS_MS[index - 1])
+                }
+                _ => format!(
+                    "{}-{}ms",
+                    HISTOGRAM_BUCKETS_MS[index - 1],
+                    HISTOGRAM_BUCKETS_MS[index]
+                ),
+            };
+            // Any bucket with frames in it gets at least some bar
+            let bar = (count * HISTOGRAM_WIDTH).div_ceil(most) as usize;
+            result.push_str(&format!("{range:>9} {count:>8} {}\n", "#".repeat(bar)));
+        }
+        result
+    }
+
+    pub fn record_frame(&mut self) -> Result<()> {
+        let now = Instant::now();
+        if let Some(last_frame) = self.last_frame {
+            if self.frame_times.len() == FRAME_TIME_HISTORY {
+                self.frame_times.pop_front();
+            }
+            let frame_time = now - last_frame;
+            self.frame_times.push_back(frame_time);
+
+            let millis = frame_time.as_millis() as u64;
+            let bucket = HISTOGRAM_BUCKETS_MS
+                .iter()
+                .position(|&max| millis < max)
+                .unwrap_or(HISTOGRAM_BUCKETS_MS.len());
+            self.histogram[bucket] += 1;
+        }
+        self.last_frame = Some(now);
+
+        self.total_frames += 1;
+
+        if self.last_second.elapsed() < ONE_SECOND {
+            self.frames_since_last_second += 1;
+        } else {
+            let mut seconds = 0;
+            // In the odd case that we jumped more than one second since the last frame...
+            while self.last_second.elapsed() >= ONE_SECOND {
+                self.last_second = self
+                    .last_second
+                    .checked_add(ONE_SECOND)
+                    .context("Time overflowed")?;
+                seconds += 1
+            }
+            self.fps = self.frames_since_last_second / seconds;
+            self.frames_since_last_second = 0;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    fs::OpenOptions,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread,
+    time::Duration,
+};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Streams raw frames to a named pipe or Unix socket, for external programs
+/// to display or process.
+///
+/// Each frame is a header of the width and height as little endian `u32`s
+/// and the frame number as a little endian `u64`, followed by the pixels as
+/// RGBA, row by row. Frame numbers count every frame Doom draws, so skipped
+/// numbers mean the consumer couldn't keep up.
+///
+/// Writing happens on a separate thread, so a slow consumer never holds up
+/// the game; frames are dropped instead. If the consumer goes away, we keep
+/// trying to reconnect.
+pub struct FrameStream {
+    sender: SyncSender<Frame>,
+    frames: u64,
+}
+
+impl FrameStream {
+    pub fn new(path: PathBuf) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        thread::spawn(move || write_frames(&path, receiver));
+        Self { sender, frames: 0 }
+    }
+
+    /// Sends an RGBA frame, unless the consumer is still busy.
+    pub fn send(&mut self, pixels: &[u8], width: u32, height: u32) {
+        let number = self.frames;
+        self.frames += 1;
+        // If the writer is still busy with the last frame, this one is
+        // dropped
+        _ = self.sender.try_send(Frame {
+            number,
+            width,
+            height,
+            pixels: pixels.to_vec(),
+        });
+    }
+}
+
+struct Frame {
+    number: u64,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+fn write_frames(path: &Path, receiver: Receiver<Frame>) {
+    let mut output = None;
+
+    while let Ok(frame) = receiver.recv() {
+        if output.is_none() {
+            match connect(path) {
+                Ok(connected) => output = Some(BufWriter::new(connected)),
+                Err(_) => {
+                    // Nobody's listening yet; frames are dropped meanwhile
+                    thread::sleep(RECONNECT_DELAY);
+                    continue;
+                }
+            }
+        }
+
+        let Some(writer) = &mut output else {
+            continue;
+        };
+        let result = writer
+            .write_all(&frame.width.to_le_bytes())
+            .and_then(|_| writer.write_all(&frame.height.to_le_bytes()))
+            .and_then(|_| writer.write_all(&frame.number.to_le_bytes()))
+            .and_then(|_| writer.write_all(&frame.pixels))
+            .and_then(|_| writer.flush());
+        if result.is_err() {
+            // The consumer went away; wait for the next one
+            output = None;
+        }
+    }
+}
+
+fn connect(path: &Path) -> io::Result<Box<dyn Write>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::{fs::FileTypeExt, net::UnixStream};
+
+        if path.metadata()?.file_type().is_socket() {
+            return Ok(Box::new(UnixStream::connect(path)?));
+        }
+    }
+
+    // Anything else is opened like a file, which works for named pipes. This
+    // blocks until there's a reader, which is fine on this thread.
+    Ok(Box::new(OpenOptions::new().write(true).open(path)?))
+}
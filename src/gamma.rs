@@ -0,0 +1,49 @@
+/// Gamma correction, for terminals where Doom looks too dark. Values above 1
+/// brighten the image.
+///
+/// The curve is worked out once into a table whenever the value changes, so
+/// applying it is just a lookup per channel.
+#[derive(Debug, Clone)]
+pub struct Gamma {
+    value: f32,
+    table: [u8; 256],
+}
+
+impl Default for Gamma {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
+}
+
+impl Gamma {
+    pub const MIN: f32 = 0.2;
+    pub const MAX: f32 = 5.0;
+
+    pub fn new(value: f32) -> Self {
+        let value = value.clamp(Self::MIN, Self::MAX);
+        let mut table = [0; 256];
+        for (input, output) in table.iter_mut().enumerate() {
+            let normalized = input as f32 / 255.0;
+            *output = (normalized.powf(1.0 / value) * 255.0).round() as u8;
+        }
+        Self { value, table }
+    }
+
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// Whether this changes anything, so we can skip it when it doesn't.
+    pub fn is_identity(&self) -> bool {
+        self.value == 1.0
+    }
+
+    /// Applies the correction to RGBA pixels, leaving alpha alone.
+    pub fn apply(&self, pixels: &mut [u8]) {
+        for pixel in pixels.chunks_exact_mut(4) {
+            for channel in &mut pixel[..3] {
+                *channel = self.table[*channel as usize];
+            }
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    io::{self, BufRead},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use ratatui::crossterm::event::{self, Event, KeyEvent, KeyEventKind, KeyModifiers};
+
+use crate::keys;
+
+/// An input event, from any source.
+#[derive(Debug, Clone, Copy)]
+pub enum InputEvent {
+    /// A key event. These go through the key bindings like keyboard input
+    /// does, so they can also control the app.
+    Key(KeyEvent),
+
+    /// An event sent to Doom as-is, with the same `(kind, code)` arguments as
+    /// `add_browser_event`.
+    Doom { kind: i32, code: i32 },
+
+    /// The terminal was resized to this many cells.
+    Resize { width: u16, height: u16 },
+}
+
+/// Something that produces input. Events from all sources are merged in
+/// `poll_events`, so e.g. a remote control can be used alongside the keyboard.
+pub trait InputSource {
+    /// Returns all the events that arrived since the last poll, without
+    /// blocking.
+    fn poll(&mut self) -> Result<Vec<InputEvent>>;
+
+    /// Waits up to `timeout` for input to arrive, without reading it. Sources
+    /// that can't wait for input just sleep.
+    fn wait(&mut self, timeout: Duration) -> Result<()> {
+        thread::sleep(timeout);
+        Ok(())
+    }
+}
+
+/// Keyboard input from the terminal.
+pub struct KeyboardInput {
+    // The most events we read in one poll, so a flood of input (e.g. a
+    // paste) can't hold up the game. The rest are read on later polls.
+    budget: usize,
+}
+
+impl KeyboardInput {
+    pub fn new(budget: Option<usize>) -> Self {
+        Self {
+            budget: budget.unwrap_or(usize::MAX),
+        }
+    }
+}
+
+impl InputSource for KeyboardInput {
+    fn poll(&mut self) -> Result<Vec<InputEvent>> {
+        let mut events = vec![];
+        let mut read = 0;
+        while read < self.budget && event::poll(Duration::ZERO)? {
+            read += 1;
+            match event::read()? {
+                Event::Key(key) => events.push(InputEvent::Key(key)),
+                Event::Resize(width, height) => events.push(InputEvent::Resize { width, height }),
+                _ => {}
+            }
+        }
+        Ok(events)
+    }
+
+    fn wait(&mut self, timeout: Duration) -> Result<()> {
+        event::poll(timeout)?;
+        Ok(())
+    }
+}
+
+/// Scripted input, read line by line from stdin, for automation. Each line is
+/// one of:
+///
+/// - `press <key>`: presses a key, using the key names from `KeyBindings`
+/// - `release <key>`: releases a key
+/// - `tap <key>`: presses and immediately releases a key
+/// - `wait <ms>`: waits before handling the next line
+///
+/// Empty lines and lines starting with `#` are ignored. Keys go through the
+/// key bindings like keyboard input does, so e.g. `tap q` quits.
+pub struct StdinInput {
+    commands: Receiver<Result<Command>>,
+    wait_until: Option<Instant>,
+    finished: bool,
+}
+
+enum Command {
+    Key(KeyEvent),
+    Wait(Duration),
+}
+
+impl StdinInput {
+    pub fn new() -> Self {
+        // Reading stdin blocks, so it's done on its own thread
+        let (sender, commands) = mpsc::channel();
+        thread::spawn(move || {
+            for (index, line) in io::stdin().lock().lines().enumerate() {
+                let commands = line
+                    .context("Failed to read from stdin")
+                    .and_then(|line| parse_command(&line))
+                    .with_context(|| format!("Invalid input on stdin line {}", index + 1));
+                let sent = match commands {
+                    Ok(commands) => commands.into_iter().all(|c| sender.send(Ok(c)).is_ok()),
+                    Err(error) => sender.send(Err(error)).is_ok(),
+                };
+                if !sent {
+                    return;
+                }
+            }
+        });
+
+        Self {
+            commands,
+            wait_until: None,
+            finished: false,
+        }
+    }
+}
+
+impl Default for StdinInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InputSource for StdinInput {
+    fn poll(&mut self) -> Result<Vec<InputEvent>> {
+        let mut events = vec![];
+
+        while !self.finished {
+            if self.wait_until.is_some_and(|until| Instant::now() < until) {
+                break;
+            }
+            self.wait_until = None;
+
+            match self.commands.try_recv() {
+                Ok(command) => match command? {
+                    Command::Key(key) => events.push(InputEvent::Key(key)),
+                    Command::Wait(duration) => self.wait_until = Some(Instant::now() + duration),
+                },
+                Err(TryRecvError::Empty) => break,
+                // Once the script is done, the game carries on without input
+                Err(TryRecvError::Disconnected) => self.finished = true,
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+fn parse_command(line: &str) -> Result<Vec<Command>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(vec![]);
+    }
+
+    let Some((command, argument)) = line.split_once(char::is_whitespace) else {
+        bail!("Expected a command and an argument, found '{line}'");
+    };
+    let argument = argument.trim();
+    let key = |kind| -> Result<Command> {
+        let code = keys::parse_key(argument)?;
+        Ok(Command::Key(KeyEvent::new_with_kind(
+            code,
+            KeyModifiers::NONE,
+            kind,
+        )))
+    };
+
+    Ok(match command {
+        "press" => vec![key(KeyEventKind::Press)?],
+        "release" => vec![key(KeyEventKind::Release)?],
+        "tap" => vec![key(KeyEventKind::Press)?, key(KeyEventKind::Release)?],
+        "wait" => {
+            let ms = argument
+                .parse()
+                .with_context(|| format!("Invalid wait time '{argument}'"))?;
+            vec![Command::Wait(Duration::from_millis(ms))]
+        }
+        _ => bail!("Unknown command '{command}'"),
+    })
+}
@@ -0,0 +1,530 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use ratatui::crossterm::event::KeyCode;
+
+/// Things a key can do to the app itself, as opposed to being sent to Doom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppAction {
+    Quit,
+    CycleProtocol,
+    ZoomIn,
+    ZoomOut,
+    Turbo,
+    ToggleLut,
+    Pause,
+    // Only while paused; otherwise the key goes to Doom as usual
+    StepTick,
+    // Holds forward, run and strafe right together while the key is held
+    StrafeRun,
+    CycleColorFilter,
+    ToggleScanlines,
+    ToggleAspectRatio,
+    GammaUp,
+    GammaDown,
+    BrightnessUp,
+    BrightnessDown,
+    ContrastUp,
+    ContrastDown,
+    // Plays back the last few seconds, or stops playing them back
+    ToggleReplay,
+    // Opens a line to type into, which is sent to Doom as key presses
+    OpenConsole,
+    ToggleHelp,
+    ToggleLogHistory,
+    // Only when zoomed; otherwise the key goes to Doom as usual
+    ResetZoom,
+    // Whether key releases go to Doom
+    ToggleReleases,
+    ToggleDebugOverlay,
+    ToggleDemoRecording,
+    ToggleAutoZoom,
+    // Also turns auto zoom on, since the axis only matters with it
+    CycleFitAxis,
+    // Fit, fill, actual size or centered
+    CycleFitMode,
+    // Switches to the next `--view-preset`
+    CyclePreset,
+    // Saves to the numbered slot, or loads from it with Shift held
+    SaveState(u8),
+    // Loads `--wasm` again, restarting the game with Shift held
+    ReloadModule,
+    // Flies through walls with WASD, for taking photos
+    TogglePhotoMode,
+    // Keeps the game running without updating the screen
+    ToggleRenderPause,
+}
+
+/// What happens when a key is pressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    App(AppAction),
+    Doom(i32),
+}
+
+/// The active key bindings. Keys without an explicit binding fall back to
+/// the default mapping in `KeyBindings::doom_key`.
+///
+/// Bindings can be loaded from a file with one `key = binding` pair per line.
+/// The key is either a single character or one of `enter`, `esc`, `tab`,
+/// `backspace`, `space`, `left`, `right`, `up`, `down`, `pause` or `f1` to
+/// `f24` (`f13` and up for keyboards and terminals that have them). The
+/// binding is one of `quit`, `protocol`, `zoom-in`, `zoom-out`, `turbo`,
+/// `lut`, `pause`, `step`, `strafe-run`, `color-filter`, `scanlines`,
+/// `aspect-ratio`, `gamma-up`, `gamma-down`, `brightness-up`,
+/// `brightness-down`, `contrast-up`, `contrast-down`, `zoom-reset`,
+/// `debug-overlay`, `key-release`, `demo-record`, `auto-zoom`, `fit-axis`,
+/// `fit-mode`, `preset`, `replay`, `console`, `help`, `log`, `reload-wasm`
+/// (restarting the game with Shift), `photo-mode`, `render-pause`,
+/// `state-1` to `state-9` (save to the slot, or load from it with Shift), a
+/// Doom key code (decimal or `0x` hex), or another key name, in which case
+/// the key acts like that key does in Doom. Lines starting with `#` are
+/// ignored.
+///
+/// Two settings control function keys. `fkey-offset = <n>` sets what's added
+/// to a function key's number to get the Doom key code, 186 by default so F1
+/// is Doom's KEY_F1 (F11 and up also skip the gap Doom leaves after F10).
+/// `reserved-fkeys = <list>` lists the function keys the app keeps for its
+/// own default bindings (F5 to F8 by default, for save states), e.g. `5 6`
+/// or `none`; the rest go to Doom. A function key bound explicitly in the
+/// file always gets that binding, whatever the reserved list says and
+/// wherever in the file either appears.
+///
+/// By default, Esc goes to Doom, where it opens the menu. With
+/// `esc-mode = app`, it's kept for the app instead: it still closes
+/// overlays and quits when pressed twice, and also leaves photo mode and
+/// stops instant replays, but Doom never sees it. `menu-key = <key>` then
+/// gives Doom's menu another key; it's the same as `<key> = esc`. Binding
+/// Esc itself in the file takes precedence over either mode.
+///
+/// ```text
+/// # Quit with Backspace instead of Q, and let Q through to Doom
+/// backspace = quit
+/// q = 113
+/// # Move with WASD
+/// w = up
+/// s = down
+/// # Give F6 to F8 back to Doom, keeping F5 for the first save slot
+/// reserved-fkeys = 5
+/// # Keep Esc for the app, and open Doom's menu with Tab
+/// esc-mode = app
+/// menu-key = tab
+/// ```
+#[derive(Debug, Clone)]
+pub struct KeyBindings {
+    bindings: HashMap<KeyCode, Binding>,
+    fkey_offset: i32,
+    // Whether Esc goes to Doom when it isn't bound explicitly
+    esc_to_doom: bool,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        let bindings = [
+            (KeyCode::Char('q'), AppAction::Quit),
+            (KeyCode::Char('Q'), AppAction::Quit),
+            (KeyCode::Char('p'), AppAction::CycleProtocol),
+            (KeyCode::Char('P'), AppAction::CycleProtocol),
+            (KeyCode::Char('+'), AppAction::ZoomIn),
+            (KeyCode::Char('-'), AppAction::ZoomOut),
+            (KeyCode::Pause, AppAction::Pause),
+            (KeyCode::Char('/'), AppAction::StepTick),
+            (KeyCode::Char('0'), AppAction::ResetZoom),
+            (KeyCode::Char('`'), AppAction::OpenConsole),
+            (KeyCode::Char('F'), AppAction::CycleFitMode),
+            (KeyCode::Char('R'), AppAction::ToggleRenderPause),
+            (KeyCode::Char(']'), AppAction::BrightnessUp),
+            (KeyCode::Char('['), AppAction::BrightnessDown),
+            (KeyCode::Char('}'), AppAction::ContrastUp),
+            (KeyCode::Char('{'), AppAction::ContrastDown),
+            (KeyCode::F(5), AppAction::SaveState(1)),
+            (KeyCode::F(6), AppAction::SaveState(2)),
+            (KeyCode::F(7), AppAction::SaveState(3)),
+            (KeyCode::F(8), AppAction::SaveState(4)),
+        ]
+        .into_iter()
+        .map(|(key, action)| (key, Binding::App(action)))
+        .collect();
+
+        Self {
+            bindings,
+            fkey_offset: DEFAULT_FKEY_OFFSET,
+            esc_to_doom: true,
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read key bindings from {}", path.display()))?;
+        Self::parse(&contents)
+            .with_context(|| format!("Invalid key bindings in {}", path.display()))
+    }
+
+    /// Parses a key bindings file. Bindings in the file are applied on top
+    /// of the defaults.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut result = Self::default();
+        let mut reserved_fkeys = None;
+        let mut lines = vec![];
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // Split on the last '=' so '=' itself can be bound
+            let Some((key, value)) = line.rsplit_once('=') else {
+                bail!("Line {}: expected `key = binding`", index + 1);
+            };
+            let (key, value) = (key.trim(), value.trim());
+            match key {
+                "fkey-offset" => {
+                    result.fkey_offset = match value.parse() {
+                        Ok(offset @ 0..=255) => offset,
+                        _ => bail!("Line {}: fkey-offset must be from 0 to 255", index + 1),
+                    }
+                }
+                "reserved-fkeys" => {
+                    reserved_fkeys = Some(
+                        parse_fkey_list(value).with_context(|| format!("Line {}", index + 1))?,
+                    )
+                }
+                "esc-mode" => {
+                    result.esc_to_doom = match value {
+                        "doom" => true,
+                        "app" => false,
+                        _ => bail!("Line {}: esc-mode must be doom or app", index + 1),
+                    }
+                }
+                // Just a binding, spelled out
+                "menu-key" => lines.push((index, value, "esc")),
+                // Settings can come anywhere in the file but change how
+                // bindings are read, so bindings wait for a second pass
+                _ => lines.push((index, key, value)),
+            }
+        }
+
+        if let Some(reserved_fkeys) = reserved_fkeys {
+            result.bindings.retain(|key, _| match key {
+                KeyCode::F(f) => reserved_fkeys.contains(f),
+                _ => true,
+            });
+        }
+
+        for (index, key, value) in lines {
+            let key = parse_key(key).with_context(|| format!("Line {}", index + 1))?;
+            let binding = parse_binding(value, result.fkey_offset)
+                .with_context(|| format!("Line {}", index + 1))?;
+
+            result.bindings.insert(key, binding);
+        }
+
+        Ok(result)
+    }
+
+    pub fn get(&self, key_code: KeyCode) -> Option<Binding> {
+        if let Some(&binding) = self.bindings.get(&key_code) {
+            return Some(binding);
+        }
+        if self.esc_is_for_app() && key_code == KeyCode::Esc {
+            return None;
+        }
+        self.doom_key(key_code).map(Binding::Doom)
+    }
+
+    /// Whether Esc is kept for the app rather than going to Doom.
+    pub fn esc_is_for_app(&self) -> bool {
+        !self.esc_to_doom && !self.bindings.contains_key(&KeyCode::Esc)
+    }
+
+    /// The Doom key a key sends when it isn't bound to anything, taking the
+    /// function key offset into account.
+    pub fn doom_key(&self, key_code: KeyCode) -> Option<i32> {
+        match key_code {
+            KeyCode::F(f) => Some(fkey_to_doom_key(f, self.fkey_offset)),
+            _ => key_code_to_doom_key(key_code),
+        }
+    }
+
+    /// The names of the keys bound to an action, for showing in the UI.
+    pub fn keys_for(&self, action: AppAction) -> Vec<String> {
+        let mut names: Vec<_> = self
+            .bindings
+            .iter()
+            .filter(|(_, binding)| **binding == Binding::App(action))
+            .map(|(key, _)| key_name(*key))
+            .collect();
+        // Sorted so the order doesn't jump around, and deduplicated since
+        // e.g. q and Q are both shown as Q
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+}
+
+fn key_name(key: KeyCode) -> String {
+    match key {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(ch) => ch.to_uppercase().to_string(),
+        KeyCode::F(f) => format!("F{f}"),
+        other => format!("{other:?}"),
+    }
+}
+
+pub fn parse_key(name: &str) -> Result<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(ch), None) = (chars.next(), chars.next()) {
+        return Ok(KeyCode::Char(ch));
+    }
+
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "enter" => KeyCode::Enter,
+        "esc" | "escape" => KeyCode::Esc,
+        "pause" => KeyCode::Pause,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "space" => KeyCode::Char(' '),
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        lower => match lower.strip_prefix('f').and_then(|f| f.parse::<u8>().ok()) {
+            Some(f @ 1..=24) => KeyCode::F(f),
+            _ => bail!("Unknown key '{name}'"),
+        },
+    })
+}
+
+/// Reads a list of function key numbers, separated by spaces or commas, or
+/// `none`.
+fn parse_fkey_list(value: &str) -> Result<HashSet<u8>> {
+    if value == "none" {
+        return Ok(HashSet::new());
+    }
+    value
+        .split([' ', ','])
+        .filter(|f| !f.is_empty())
+        .map(|f| match f.trim_start_matches(['f', 'F']).parse() {
+            Ok(f @ 1..=24) => Ok(f),
+            _ => bail!("Unknown function key '{f}', expected 1 to 24"),
+        })
+        .collect()
+}
+
+fn parse_binding(value: &str, fkey_offset: i32) -> Result<Binding> {
+    Ok(match value {
+        "quit" => Binding::App(AppAction::Quit),
+        "protocol" => Binding::App(AppAction::CycleProtocol),
+        "zoom-in" => Binding::App(AppAction::ZoomIn),
+        "zoom-out" => Binding::App(AppAction::ZoomOut),
+        "turbo" => Binding::App(AppAction::Turbo),
+        "lut" => Binding::App(AppAction::ToggleLut),
+        "pause" => Binding::App(AppAction::Pause),
+        "step" => Binding::App(AppAction::StepTick),
+        "strafe-run" => Binding::App(AppAction::StrafeRun),
+        "color-filter" => Binding::App(AppAction::CycleColorFilter),
+        "scanlines" => Binding::App(AppAction::ToggleScanlines),
+        "aspect-ratio" => Binding::App(AppAction::ToggleAspectRatio),
+        "gamma-up" => Binding::App(AppAction::GammaUp),
+        "gamma-down" => Binding::App(AppAction::GammaDown),
+        "brightness-up" => Binding::App(AppAction::BrightnessUp),
+        "brightness-down" => Binding::App(AppAction::BrightnessDown),
+        "contrast-up" => Binding::App(AppAction::ContrastUp),
+        "contrast-down" => Binding::App(AppAction::ContrastDown),
+        "replay" => Binding::App(AppAction::ToggleReplay),
+        "console" => Binding::App(AppAction::OpenConsole),
+        "reload-wasm" => Binding::App(AppAction::ReloadModule),
+        "photo-mode" => Binding::App(AppAction::TogglePhotoMode),
+        "help" => Binding::App(AppAction::ToggleHelp),
+        "log" => Binding::App(AppAction::ToggleLogHistory),
+        "zoom-reset" => Binding::App(AppAction::ResetZoom),
+        "debug-overlay" => Binding::App(AppAction::ToggleDebugOverlay),
+        "key-release" => Binding::App(AppAction::ToggleReleases),
+        "demo-record" => Binding::App(AppAction::ToggleDemoRecording),
+        "auto-zoom" => Binding::App(AppAction::ToggleAutoZoom),
+        "fit-axis" => Binding::App(AppAction::CycleFitAxis),
+        "fit-mode" => Binding::App(AppAction::CycleFitMode),
+        "render-pause" => Binding::App(AppAction::ToggleRenderPause),
+        "preset" => Binding::App(AppAction::CyclePreset),
+        _ if value.starts_with("state-") => match value["state-".len()..].parse() {
+            Ok(slot @ 1..=9) => Binding::App(AppAction::SaveState(slot)),
+            _ => bail!("Unknown state slot in '{value}', expected 1 to 9"),
+        },
+        _ => {
+            let code = match value.strip_prefix("0x") {
+                Some(hex) => i32::from_str_radix(hex, 16).ok(),
+                None => value.parse::<i32>().ok(),
+            };
+            match code {
+                Some(code) => Binding::Doom(code),
+                None => {
+                    let key =
+                        parse_key(value).with_context(|| format!("Unknown binding '{value}'"))?;
+                    let code = match key {
+                        KeyCode::F(f) => Some(fkey_to_doom_key(f, fkey_offset)),
+                        key => key_code_to_doom_key(key),
+                    };
+                    match code {
+                        Some(code) => Binding::Doom(code),
+                        None => bail!("'{value}' doesn't map to a Doom key"),
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// What's added to a function key's number to get its Doom key code, so F1
+/// is Doom's KEY_F1 (0x80 + 0x3b).
+const DEFAULT_FKEY_OFFSET: i32 = 186;
+
+/// The Doom key code for function key `f`. Doom's F11 and F12 don't follow
+/// on from F10 (0x80 + 0x44) but sit at 0x80 + 0x57 and 0x80 + 0x58, so keys
+/// past F10 are shifted up by the gap.
+fn fkey_to_doom_key(f: u8, fkey_offset: i32) -> i32 {
+    let gap = if f > 10 { 0x57 - 0x45 } else { 0 };
+    f as i32 + fkey_offset + gap
+}
+
+// var keys = { KEY_ESCAPE: 27, KEY_TAB: 9 }
+pub fn key_code_to_doom_key(key_code: KeyCode) -> Option<i32> {
+    match key_code {
+        KeyCode::Enter => Some(13),
+        KeyCode::Backspace => Some(127),
+        KeyCode::Char(' ') => Some(32),
+        KeyCode::Left => Some(0xac),
+        KeyCode::Right => Some(0xae),
+        KeyCode::Up => Some(0xad),
+        KeyCode::Down => Some(0xaf),
+        KeyCode::Tab => Some(9),
+        KeyCode::Esc => Some(27),
+
+        // Since reading individual modifiers isn't globally supported, we map
+        // z, x, c, v to ctrl, shift, alt, space in that order. Space is mapped
+        // so you can just use four nearby keys and it doesn't get too awkward.
+        KeyCode::Char('z') => Some(0x80 + 0x1d), // ctrl
+        KeyCode::Char('x') => Some(0x80 + 0x38), // alt
+        KeyCode::Char('c') => Some(16),          // shift
+        KeyCode::Char('v') => Some(32),          // space, also mapped above
+
+        KeyCode::Char(ch) => Some(ch as i32),
+        KeyCode::F(f) => Some(fkey_to_doom_key(f, DEFAULT_FKEY_OFFSET)),
+
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bindings() {
+        let bindings = KeyBindings::parse(
+            "# Quit with Backspace instead of Q\n\
+             backspace = quit\n\
+             q = 113\n\
+             w = up\n\
+             = = zoom-in\n\
+             f13 = 0x1f\n\
+             k = state-3\n",
+        )
+        .unwrap();
+        assert_eq!(
+            bindings.get(KeyCode::Backspace),
+            Some(Binding::App(AppAction::Quit))
+        );
+        assert_eq!(bindings.get(KeyCode::Char('q')), Some(Binding::Doom(113)));
+        assert_eq!(bindings.get(KeyCode::Char('w')), Some(Binding::Doom(0xad)));
+        assert_eq!(
+            bindings.get(KeyCode::Char('=')),
+            Some(Binding::App(AppAction::ZoomIn))
+        );
+        assert_eq!(bindings.get(KeyCode::F(13)), Some(Binding::Doom(0x1f)));
+        assert_eq!(
+            bindings.get(KeyCode::Char('k')),
+            Some(Binding::App(AppAction::SaveState(3)))
+        );
+        // Untouched defaults stay
+        assert_eq!(
+            bindings.get(KeyCode::Char('p')),
+            Some(Binding::App(AppAction::CycleProtocol))
+        );
+    }
+
+    #[test]
+    fn applies_fkey_settings_wherever_they_are() {
+        let bindings = KeyBindings::parse("f2 = f1\nfkey-offset = 100").unwrap();
+        assert_eq!(bindings.get(KeyCode::F(2)), Some(Binding::Doom(101)));
+        assert_eq!(bindings.get(KeyCode::F(3)), Some(Binding::Doom(103)));
+
+        let bindings = KeyBindings::parse("reserved-fkeys = 5").unwrap();
+        assert_eq!(
+            bindings.get(KeyCode::F(5)),
+            Some(Binding::App(AppAction::SaveState(1)))
+        );
+        assert_eq!(
+            bindings.get(KeyCode::F(6)),
+            Some(Binding::Doom(6 + DEFAULT_FKEY_OFFSET))
+        );
+        assert_eq!(key_code_to_doom_key(KeyCode::F(1)), Some(0x80 + 0x3b));
+        assert_eq!(key_code_to_doom_key(KeyCode::F(10)), Some(0x80 + 0x44));
+        assert_eq!(key_code_to_doom_key(KeyCode::F(11)), Some(0x80 + 0x57));
+        assert_eq!(key_code_to_doom_key(KeyCode::F(12)), Some(0x80 + 0x58));
+
+        let bindings = KeyBindings::parse("f7 = state-2\nreserved-fkeys = none").unwrap();
+        assert_eq!(
+            bindings.get(KeyCode::F(7)),
+            Some(Binding::App(AppAction::SaveState(2)))
+        );
+    }
+
+    #[test]
+    fn keeps_esc_for_the_app_when_asked() {
+        let bindings = KeyBindings::default();
+        assert_eq!(bindings.get(KeyCode::Esc), Some(Binding::Doom(27)));
+        assert!(!bindings.esc_is_for_app());
+
+        let bindings = KeyBindings::parse("esc-mode = app\nmenu-key = tab").unwrap();
+        assert_eq!(bindings.get(KeyCode::Esc), None);
+        assert_eq!(bindings.get(KeyCode::Tab), Some(Binding::Doom(27)));
+        assert!(bindings.esc_is_for_app());
+
+        let bindings = KeyBindings::parse("esc-mode = app\nesc = quit").unwrap();
+        assert_eq!(
+            bindings.get(KeyCode::Esc),
+            Some(Binding::App(AppAction::Quit))
+        );
+        assert!(!bindings.esc_is_for_app());
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        for contents in [
+            "q",
+            "q = teleport",
+            "q = state-10",
+            "nokey = quit",
+            "f25 = quit",
+            "fkey-offset = 256",
+            "reserved-fkeys = 25",
+            "esc-mode = maybe",
+        ] {
+            assert!(KeyBindings::parse(contents).is_err(), "{contents}");
+        }
+    }
+
+    #[test]
+    fn names_the_keys_for_an_action() {
+        let bindings = KeyBindings::parse("f9 = quit").unwrap();
+        assert_eq!(bindings.keys_for(AppAction::Quit), ["F9", "Q"]);
+        assert!(bindings.keys_for(AppAction::OpenConsole).is_empty());
+    }
+}
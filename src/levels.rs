@@ -0,0 +1,62 @@
+/// Brightness and contrast, for terminals where Doom's dark corners are hard
+/// to make out. Brightness is added to every channel, as a fraction of full
+/// white, and contrast stretches the channels away from mid gray.
+///
+/// Like `Gamma`, both are worked out once into a table whenever they change.
+#[derive(Debug, Clone)]
+pub struct Levels {
+    brightness: f32,
+    contrast: f32,
+    table: [u8; 256],
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        Self::new(0.0, 1.0)
+    }
+}
+
+impl Levels {
+    pub const MIN_BRIGHTNESS: f32 = -0.5;
+    pub const MAX_BRIGHTNESS: f32 = 0.5;
+    pub const MIN_CONTRAST: f32 = 0.2;
+    pub const MAX_CONTRAST: f32 = 3.0;
+
+    pub fn new(brightness: f32, contrast: f32) -> Self {
+        let brightness = brightness.clamp(Self::MIN_BRIGHTNESS, Self::MAX_BRIGHTNESS);
+        let contrast = contrast.clamp(Self::MIN_CONTRAST, Self::MAX_CONTRAST);
+        let mut table = [0; 256];
+        for (input, output) in table.iter_mut().enumerate() {
+            let normalized = input as f32 / 255.0;
+            let adjusted = (normalized - 0.5) * contrast + 0.5 + brightness;
+            *output = (adjusted.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+        Self {
+            brightness,
+            contrast,
+            table,
+        }
+    }
+
+    pub fn brightness(&self) -> f32 {
+        self.brightness
+    }
+
+    pub fn contrast(&self) -> f32 {
+        self.contrast
+    }
+
+    /// Whether this changes anything, so we can skip it when it doesn't.
+    pub fn is_identity(&self) -> bool {
+        self.brightness == 0.0 && self.contrast == 1.0
+    }
+
+    /// Applies the adjustment to RGBA pixels, leaving alpha alone.
+    pub fn apply(&self, pixels: &mut [u8]) {
+        for pixel in pixels.chunks_exact_mut(4) {
+            for channel in &mut pixel[..3] {
+                *channel = self.table[*channel as usize];
+            }
+        }
+    }
+}
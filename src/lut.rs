@@ -0,0 +1,129 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use image::RgbaImage;
+
+/// A 3D color lookup table, loaded from a `.cube` file, for color grading.
+#[derive(Debug, Clone)]
+pub struct Lut {
+    size: usize,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    // Output colors, with red changing fastest, then green, then blue
+    table: Vec<[f32; 3]>,
+}
+
+impl Lut {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read LUT from {}", path.display()))?;
+        Self::parse(&contents).with_context(|| format!("Invalid LUT in {}", path.display()))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut table = vec![];
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("TITLE") {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap_or_default();
+            match keyword {
+                "LUT_3D_SIZE" => {
+                    let value = words.next().and_then(|size| size.parse().ok());
+                    match value {
+                        Some(value @ 2..=256) => size = Some(value),
+                        _ => bail!("Line {}: LUT_3D_SIZE must be from 2 to 256", index + 1),
+                    }
+                }
+                "LUT_1D_SIZE" => bail!("Line {}: only 3D LUTs are supported", index + 1),
+                "DOMAIN_MIN" => domain_min = parse_triple(line, words, index)?,
+                "DOMAIN_MAX" => domain_max = parse_triple(line, words, index)?,
+                _ => table.push(parse_triple(line, line.split_whitespace(), index)?),
+            }
+        }
+
+        let Some(size) = size else {
+            bail!("Missing LUT_3D_SIZE");
+        };
+        if table.len() != size * size * size {
+            bail!(
+                "Expected {} entries for a LUT of size {size}, found {}",
+                size * size * size,
+                table.len()
+            );
+        }
+        if (0..3).any(|i| domain_max[i] <= domain_min[i]) {
+            bail!("DOMAIN_MAX must be larger than DOMAIN_MIN");
+        }
+
+        Ok(Self {
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    pub fn apply(&self, image: &mut RgbaImage) {
+        for pixel in image.pixels_mut() {
+            let color = self
+                .lookup([pixel[0], pixel[1], pixel[2]])
+                .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+            pixel.0[..3].copy_from_slice(&color);
+        }
+    }
+
+    // Trilinear interpolation between the 8 entries around the color
+    fn lookup(&self, color: [u8; 3]) -> [f32; 3] {
+        let max = (self.size - 1) as f32;
+        let position: [f32; 3] = std::array::from_fn(|i| {
+            let value = color[i] as f32 / 255.0;
+            let normalized =
+                (value - self.domain_min[i]) / (self.domain_max[i] - self.domain_min[i]);
+            normalized.clamp(0.0, 1.0) * max
+        });
+        let low = position.map(|p| p.floor() as usize);
+        let high = low.map(|l| (l + 1).min(self.size - 1));
+        let fraction: [f32; 3] = std::array::from_fn(|i| position[i] - low[i] as f32);
+
+        let mut result = [0.0; 3];
+        for corner in 0..8 {
+            let pick = |axis: usize| corner & (1 << axis) != 0;
+            let index: [usize; 3] = std::array::from_fn(|i| if pick(i) { high[i] } else { low[i] });
+            let weight: f32 = (0..3)
+                .map(|i| {
+                    if pick(i) {
+                        fraction[i]
+                    } else {
+                        1.0 - fraction[i]
+                    }
+                })
+                .product();
+            let entry =
+                self.table[index[0] + index[1] * self.size + index[2] * self.size * self.size];
+            for (result, entry) in result.iter_mut().zip(entry) {
+                *result += entry * weight;
+            }
+        }
+        result
+    }
+}
+
+fn parse_triple<'a>(
+    line: &str,
+    mut words: impl Iterator<Item = &'a str>,
+    index: usize,
+) -> Result<[f32; 3]> {
+    let mut next = || words.next().and_then(|word| word.parse::<f32>().ok());
+    match (next(), next(), next()) {
+        (Some(a), Some(b), Some(c)) => Ok([a, b, c]),
+        _ => bail!("Line {}: expected three numbers, found '{line}'", index + 1),
+    }
+}
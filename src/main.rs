@@ -1,35 +1,241 @@
+mod ascii;
+mod backend;
+mod cli;
+mod clock;
+mod color_filter;
+mod config;
+mod demo;
+mod fit;
+mod frame_dump;
+mod frame_hash;
+mod frame_stats;
+mod frame_stream;
+mod gamma;
+mod input;
+mod keys;
+mod levels;
+mod lut;
+mod margins;
+mod menu;
+mod overlay;
+mod palette;
+mod pixel_format;
+mod preset;
+mod render_cache;
+mod render_thread;
+mod replay;
+mod save_state;
+mod snapshot;
+mod status_file;
+mod terminal;
+mod wad;
+mod watchdog;
+mod zoom;
+
 use std::{
+    borrow::Cow,
     cell::RefCell,
+    collections::{HashMap, VecDeque},
+    fs,
+    io::{self, IsTerminal, Write},
+    path::PathBuf,
     rc::Rc,
     thread,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use image::imageops::FilterType;
 use ratatui::{
     buffer::Buffer,
     crossterm::{
         event::{
-            self, Event, KeyCode, KeyEventKind, KeyboardEnhancementFlags,
+            self, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
             PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
         },
         execute,
+        terminal::{disable_raw_mode, enable_raw_mode, supports_keyboard_enhancement},
     },
     layout::Rect,
-    style::Stylize,
+    style::{Color, Style, Stylize},
     symbols::border,
     text::{Line, Text},
-    widgets::{Block, Paragraph, Widget},
+    widgets::{Block, Clear, Paragraph, Widget},
     DefaultTerminal,
 };
-use ratatui_image::{picker::Picker, protocol::Protocol, FontSize};
+use ratatui_image::{
+    picker::{Picker, ProtocolType},
+    FontSize,
+};
 use wasmer::{
-    imports, Function, FunctionEnv, FunctionEnvMut, Instance, Memory, MemoryType, Module, Store,
-    TypedFunction, WasmSlice,
+    imports, ExternType, Function, FunctionEnv, FunctionEnvMut, FunctionType, Instance, Memory,
+    MemoryType, MemoryView, Module, Store, Type, TypedFunction, WasmSlice, WASM_PAGE_SIZE,
 };
 
+use ascii::AsciiRenderer;
+use backend::{Backend, ImageBackend};
+use cli::Options;
+use clock::GameClock;
+use color_filter::ColorFilter;
+use demo::DemoRecorder;
+use fit::{FitAxis, FitMode};
+use frame_dump::FrameDump;
+use frame_hash::FrameHashes;
+use frame_stats::FrameStats;
+use frame_stream::FrameStream;
+use gamma::Gamma;
+use input::{InputEvent, InputSource, KeyboardInput, StdinInput};
+use keys::{AppAction, Binding, KeyBindings};
+use levels::Levels;
+use lut::Lut;
+use margins::Margins;
+use menu::MenuDetector;
+use overlay::{Overlay, OverlayKind};
+use palette::Palettes;
+use pixel_format::PixelFormat;
+use preset::{PresetZoom, ViewPreset};
+use render_cache::RenderCache;
+use render_thread::RenderThread;
+use replay::ReplayBuffer;
+use save_state::SaveState;
+use snapshot::Snapshot;
+use status_file::{Status, StatusFile};
+use wad::Pwad;
+use watchdog::DrawWatchdog;
+use zoom::ZoomStrategy;
+
 const WASM_BYTES: &[u8] = include_bytes!("../doom.wasm");
+
+// The font size we assume when the terminal can't tell us its own
+const DEFAULT_FONT_SIZE: FontSize = (8, 16);
 const MEMORY_PAGES: u32 = 102;
+// The most pages a 32-bit WASM memory can have, i.e. 4GiB
+const MAX_MEMORY_PAGES: u32 = 65536;
+
+// Two presses of Esc within this window ask whether to quit the app. Esc
+// still goes to Doom either way, since it opens and backs out of menus.
+const DOUBLE_ESCAPE_TIMEOUT: Duration = Duration::from_millis(300);
+
+// Doom's TICRATE. Doom derives its ticks from `js_milliseconds_since_start`,
+// so we can work out the current tick the same way.
+const TICKS_PER_SECOND: u128 = 35;
+
+// How much faster the game runs in turbo mode
+const TURBO_SPEED: u32 = 4;
+
+// The lowest adaptive quality renders at a quarter of the resolution
+const MAX_QUALITY_DIVISOR: u16 = 4;
+
+// Forward, run and strafe right, for the strafe-run assist. Run is what `c`
+// maps to by default, and strafe right is vanilla Doom's `.`.
+const STRAFE_RUN_KEYS: [i32; 3] = [0xad, 16, b'.' as i32];
+
+// Long enough that keyboard auto-repeat kicks in well before it, so held
+// keys keep getting events and aren't released by mistake
+const DEFAULT_KEY_RELEASE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// The left and right arrow keys, as Doom sees them
+const TURN_KEYS: [i32; 2] = [0xac, 0xae];
+
+// While in a menu, we render at about 5 FPS and check for input every 10ms
+const MENU_RENDER_INTERVAL: Duration = Duration::from_millis(200);
+const MENU_SLEEP: Duration = Duration::from_millis(10);
+
+// With --low-latency, the longest we wait for input in one go, so held keys
+// are still released and the screen refreshed on time
+const MAX_INPUT_WAIT: Duration = Duration::from_millis(10);
+
+const DEATH_FLASH_DURATION: Duration = Duration::from_secs(1);
+
+// A loop iteration taking this long means we weren't running at all, rather
+// than just running slowly
+const SUSPEND_GAP: Duration = Duration::from_secs(1);
+
+// Below this, there's no room for the image inside the border and the log
+// line, so we just say so
+const MIN_TERMINAL_WIDTH: u16 = 16;
+const MIN_TERMINAL_HEIGHT: u16 = 6;
+
+// How many log lines the log overlay goes back
+const LOG_HISTORY_LINES: usize = 200;
+
+// How many of the events sent to Doom `--show-doom-keys` shows
+const SENT_EVENTS_SHOWN: usize = 8;
+
+// Everything in the help overlay, other than the built in keys and the save
+// state slots
+const HELP_ACTIONS: &[(&str, AppAction)] = &[
+    ("Quit", AppAction::Quit),
+    ("Switch image protocol", AppAction::CycleProtocol),
+    ("Zoom in", AppAction::ZoomIn),
+    ("Zoom out", AppAction::ZoomOut),
+    ("Reset zoom", AppAction::ResetZoom),
+    ("Auto zoom", AppAction::ToggleAutoZoom),
+    ("Switch fit axis", AppAction::CycleFitAxis),
+    ("Switch fit mode", AppAction::CycleFitMode),
+    ("Switch view preset", AppAction::CyclePreset),
+    ("Turbo", AppAction::Turbo),
+    ("Pause", AppAction::Pause),
+    ("Step one tick while paused", AppAction::StepTick),
+    ("Strafe-run while held", AppAction::StrafeRun),
+    ("Toggle LUT", AppAction::ToggleLut),
+    ("Switch color filter", AppAction::CycleColorFilter),
+    ("Toggle scanlines", AppAction::ToggleScanlines),
+    (
+        "Toggle aspect ratio correction",
+        AppAction::ToggleAspectRatio,
+    ),
+    ("Gamma up", AppAction::GammaUp),
+    ("Gamma down", AppAction::GammaDown),
+    ("Brightness up", AppAction::BrightnessUp),
+    ("Brightness down", AppAction::BrightnessDown),
+    ("Contrast up", AppAction::ContrastUp),
+    ("Contrast down", AppAction::ContrastDown),
+    ("Ignore or forward key releases", AppAction::ToggleReleases),
+    ("Debug overlay", AppAction::ToggleDebugOverlay),
+    ("Record a demo", AppAction::ToggleDemoRecording),
+    ("Photo mode", AppAction::TogglePhotoMode),
+    ("Pause rendering", AppAction::ToggleRenderPause),
+    ("Reload the WASM module", AppAction::ReloadModule),
+    ("Instant replay", AppAction::ToggleReplay),
+    ("Type a cheat or command", AppAction::OpenConsole),
+    ("This help", AppAction::ToggleHelp),
+    ("Log history", AppAction::ToggleLogHistory),
+];
+
+// How much the gamma keys change the gamma by
+const GAMMA_STEP: f32 = 0.1;
+
+// How much the brightness and contrast keys change them by
+const LEVELS_STEP: f32 = 0.1;
+
+// How bright scanlines are, out of 256
+const SCANLINE_BRIGHTNESS: u16 = 160;
+
+// Log lines older than this are cleared, so stale messages don't linger
+const DEFAULT_LOG_RETENTION_SECS: u64 = 30;
+
+// Below this many frames a second, the FPS in the title turns red
+const DEFAULT_LOW_FPS: u16 = 20;
+
+// The longest console line. Each character is two events in Doom's event
+// queue, which only holds 64.
+const MAX_CONSOLE_LENGTH: usize = 30;
+
+// How often the status file is written, by default
+const DEFAULT_STATUS_INTERVAL_MS: u64 = 1000;
+
+/// How big the screen is in snapshot mode, unless told otherwise.
+const DEFAULT_SNAPSHOT_SIZE: (u16, u16) = (80, 25);
+
+/// How many ticks a snapshot run lasts, unless told otherwise: 10 seconds.
+const DEFAULT_SNAPSHOT_TICKS: u32 = 350;
+
+/// How many ticks the self-test runs for Doom to draw something.
+const SELF_TEST_TICKS: u32 = 35;
+
+// How long the notice for saving or loading a state stays up
+const STATE_NOTICE_DURATION: Duration = Duration::from_secs(2);
 
 // This needs to be static so it's accessible to the rendering WASM import function.
 // Since we only have one thread, we can safely use an Rc. However, Rust doesn't know
@@ -38,25 +244,455 @@ thread_local! {
     static TERMINAL: Rc<RefCell<Option<DefaultTerminal>>> = Rc::new(RefCell::new(None));
 }
 
+/// Everything an encoded frame depends on: the frame's content, and the
+/// settings that change how it's encoded. Protocol and zoom changes go
+/// through `rebuild_picker`, which clears the cache instead.
+type RenderKey = (u64, u16, bool, FitAxis, FitMode, Option<Rect>);
+
 /// The app status, modified in input functions and WASM imports. This
 /// is placed inside a Wasmer FunctionEnv.
 struct DoomApp {
     exit: bool,
+    safe_mode: bool,
+    last_escape_press: Option<Instant>,
+    // Asked whether to quit after a double Esc, waiting for the answer
+    confirming_quit: bool,
+    // Keys Doom thinks are held, with when we last heard from each
+    held_keys: HashMap<i32, Instant>,
+    key_release_timeout: Option<Duration>,
+    // Whether key releases from the terminal go to Doom. When they don't,
+    // keys are only released once they go stale.
+    forward_releases: bool,
+
+    key_bindings: KeyBindings,
+    key_bindings_path: Option<PathBuf>,
+    // Log keys that aren't bound to anything and don't map to a Doom key
+    log_unknown_keys: bool,
 
     last_log_line: Option<String>,
     last_log_error: bool,
+    last_log_at: Instant,
+    // How long a log line stays up. If unset, it stays until the next one.
+    log_retention: Option<Duration>,
+    // The FPS below which we warn that the game isn't running smoothly
+    low_fps: Option<u16>,
+    // The most recent log lines, oldest first, and whether they're errors
+    log_history: VecDeque<(String, bool)>,
+    overlay: Option<Overlay>,
+    // The line being typed into the console, while it's open
+    console: Option<String>,
+    // While in photo mode, the last frame as shown, to save as a photo
+    photo_mode: bool,
+    photo_frame: Option<image::RgbaImage>,
+    // Doom can log lots of lines a frame, but only the last one is shown, so
+    // its lines are kept as raw bytes and only converted when rendering
+    pending_log_line: Option<(Vec<u8>, bool)>,
 
-    image_picker: Picker,
-    current_frame: Option<Protocol>,
+    image_backend: Backend,
+    current_frame: Option<<Backend as ImageBackend>::Frame>,
+    // What `current_frame` was encoded from, so an identical frame can
+    // reuse it
+    render_cache: RenderCache<RenderKey>,
     default_font_size: FontSize,
     zoom: u16,
+    // Pick the biggest zoom that fits the terminal, instead of the user
+    auto_zoom: bool,
+    // Never zoom past `max_zoom`
+    clamp_zoom: bool,
+    // Ask Doom for a resolution that matches the terminal when it's resized
+    match_resolution: bool,
+    fit_axis: FitAxis,
+    fit_mode: FitMode,
+    margins: Margins,
+    // The `--view-preset`s, and which one was applied last
+    presets: Vec<ViewPreset>,
+    preset_index: Option<usize>,
+    // The image is scaled down by this much before rendering, to keep up
+    // with the frame budget
+    quality_divisor: u16,
+    frame_budget: Option<Duration>,
+    render_time: Option<Duration>,
+    background: Option<Color>,
+    border_set: border::Set,
+    terminal_background: Option<Color>,
+    protocol_zoom: HashMap<String, u16>,
+    // How zooming works for each protocol, by lowercase name
+    zoom_strategies: HashMap<String, ZoomStrategy>,
+    stats_only: bool,
+    debug_overlay: bool,
+    invert_errors: bool,
+
+    // ASCII rendering comes after the image protocols when cycling through
+    // them. It's rendered from the raw frame at draw time, since the output
+    // depends on the area it's drawn into.
+    ascii: bool,
+    ascii_renderer: AsciiRenderer,
+    ascii_frame: Option<image::RgbaImage>,
+
+    frame_dump: Option<FrameDump>,
+    frame_hashes: Option<FrameHashes>,
+    frame_stream: Option<FrameStream>,
+    status_file: Option<StatusFile>,
+    show_hash: bool,
+    // The last events sent to Doom, newest last, with `--show-doom-keys`
+    sent_events: Option<VecDeque<(i32, i32)>>,
+
+    lut: Option<Lut>,
+    lut_enabled: bool,
+    color_filter: Option<ColorFilter>,
+    scanlines: bool,
+    // Stretch the image to the 4:3 shape Doom was drawn for
+    aspect_correction: bool,
+    gamma: Gamma,
+    levels: Levels,
 
     started_at: Instant,
+    clock: GameClock,
+    turbo: bool,
+    paused: bool,
+    // Paused until the first key press, with a prompt saying so
+    waiting_to_start: bool,
+    // The game keeps running, but the screen isn't updated
+    render_paused: bool,
+    // Whether Doom drew something new while rendering was paused, so the
+    // next frame is shown even if it didn't change
+    missed_changes: bool,
+    memory: Memory,
+    // The size of `memory` in pages, as of the last step, and the most it
+    // can grow to. Kept here since rendering can't get at the store.
+    memory_pages: u32,
+    max_memory_pages: u32,
+
+    seed: u32,
+    pixel_format: PixelFormat,
+    // The framebuffer's size, which builds with adjustable resolution report
+    // through `screen_size`, asked before every frame
+    frame_size: (u32, u32),
+    screen_size: Option<(TypedFunction<(), i32>, TypedFunction<(), i32>)>,
+    // For indexed framebuffers, and which of them Doom last asked for
+    palettes: Option<Palettes>,
+    palette_index: usize,
+    // Handed to builds that import the `js_pwad_*` functions, in order
+    pwads: Vec<Pwad>,
+
+    death_alert: Option<u32>,
+    player_health: Option<i32>,
+    death_flash_started: Option<Instant>,
+
+    // The tick we last sent the strafe-run keys on, while the assist key is
+    // held
+    strafe_run_tick: Option<u128>,
+
+    // With turn coalescing, turn key events are held back until the end of
+    // the tick, and then forwarded with at most this many presses each
+    turn_coalesce: Option<u32>,
+    pending_turns: HashMap<i32, PendingTurn>,
+    turn_tick: u128,
+
+    episode_address: Option<u32>,
+    map_address: Option<u32>,
+    // The episode, if the game has them, and the map
+    current_map: Option<(Option<u8>, u8)>,
+
+    demo: Option<DemoRecorder>,
+    // The last tick recorded into the demo
+    demo_tick: u128,
+    demo_dir: PathBuf,
+    demo_skill: u8,
+
+    // Where save state slots are kept, as `slot<n>.state`
+    state_dir: PathBuf,
+    // What we last saved or loaded, shown over the game for a moment
+    state_notice: Option<(String, Instant)>,
+
+    replay_buffer: Option<ReplayBuffer>,
+    // The next frame to replay, if we're replaying, and when the last one
+    // was shown
+    replay_position: Option<usize>,
+    replay_frame_at: Option<Instant>,
+    // Whether the game was paused before the replay, to go back to after
+    replay_was_paused: bool,
+
+    menu_detector: Option<MenuDetector>,
+    in_menu: bool,
+
+    frame_stats: FrameStats,
+
+    last_render: Instant,
+    // If set, we re-render at least this often, even if Doom hasn't drawn
+    // anything new
+    refresh_interval: Option<Duration>,
+    // Frames Doom draws sooner than this after the last render are skipped
+    min_draw_interval: Option<Duration>,
+    // The most game time Doom is left to catch up on in one step, and how
+    // many ticks were dropped to keep to it
+    max_lag: Option<Duration>,
+    dropped_ticks: u64,
+    // Wait for input in the main loop, rather than sleeping
+    low_latency: bool,
+    // Don't sleep or wait in the main loop at all
+    no_sleep: bool,
+    adaptive_refresh: bool,
+    terminal_draw_time: Option<Duration>,
+    // Frames skipped since the last one drawn, because the terminal was
+    // behind
+    frames_skipped: u32,
+    draw_watchdog: Option<DrawWatchdog>,
+    // When set, the terminal belongs to this thread instead of `TERMINAL`
+    render_thread: Option<RenderThread>,
+    // When set, the final screen is saved instead of being shown
+    snapshot: Option<Snapshot>,
+    // There's no terminal at all, and nothing is drawn
+    headless: bool,
+}
+
+/// What the app is built from besides the options: what was loaded from
+/// disk and found out about the terminal before starting.
+struct Resources {
     memory: Memory,
+    picker: Picker,
+    terminal_background: Option<Color>,
+    // Whether the terminal reports key releases
+    reports_releases: bool,
+    scripted_input: bool,
+    headless: bool,
+    key_bindings: KeyBindings,
+    lut: Option<Lut>,
+    pwads: Vec<Pwad>,
+    palettes: Option<Palettes>,
+    frame_dump: Option<FrameDump>,
+    frame_hashes: Option<FrameHashes>,
+    render_thread: Option<RenderThread>,
+    snapshot: Option<Snapshot>,
+}
+
+impl DoomApp {
+    fn new(options: &Options, resources: Resources) -> Self {
+        let Resources {
+            memory,
+            picker,
+            terminal_background,
+            reports_releases,
+            scripted_input,
+            headless,
+            key_bindings,
+            lut,
+            pwads,
+            palettes,
+            frame_dump,
+            frame_hashes,
+            render_thread,
+            snapshot,
+        } = resources;
+
+        Self {
+            exit: false,
+            safe_mode: options.safe,
+            last_escape_press: None,
+            confirming_quit: false,
+            held_keys: HashMap::new(),
+            // Scripted input holds keys for as long as it likes, and always
+            // releases them itself. Terminals that report releases don't
+            // need it either, unless it's asked for.
+            key_release_timeout: match options.key_release_ms {
+                _ if scripted_input => None,
+                Some(0) => None,
+                Some(ms) => Some(Duration::from_millis(ms.into())),
+                None if reports_releases => None,
+                None => Some(DEFAULT_KEY_RELEASE_TIMEOUT),
+            },
+            forward_releases: !options.ignore_releases,
+
+            key_bindings,
+            key_bindings_path: options.keys.clone(),
+            log_unknown_keys: options.log_unknown_keys,
+
+            last_log_line: frame_dump
+                .as_ref()
+                .map(|dump| format!("Dumping frames to {}", dump.dir().display())),
+            last_log_error: false,
+            last_log_at: Instant::now(),
+            log_retention: match options.log_retention_secs {
+                Some(0) => None,
+                secs => Some(Duration::from_secs(
+                    secs.map_or(DEFAULT_LOG_RETENTION_SECS, u64::from),
+                )),
+            },
+            low_fps: match options.low_fps {
+                Some(0) => None,
+                fps => Some(fps.map_or(DEFAULT_LOW_FPS, |fps| {
+                    u16::try_from(fps).unwrap_or(u16::MAX)
+                })),
+            },
+            pending_log_line: None,
+            log_history: VecDeque::with_capacity(LOG_HISTORY_LINES),
+            overlay: None,
+            console: None,
+            photo_mode: false,
+            photo_frame: None,
+
+            default_font_size: picker.font_size(),
+            image_backend: Backend::new(picker),
+            current_frame: None,
+            render_cache: RenderCache::default(),
+            zoom: 1,
+            auto_zoom: options.auto_zoom,
+            clamp_zoom: !options.no_clamp_zoom,
+            match_resolution: options.match_resolution,
+            fit_axis: options.fit_axis,
+            fit_mode: options.fit_mode,
+            margins: options.margins,
+            presets: options.view_presets.clone(),
+            preset_index: None,
+            quality_divisor: 1,
+            frame_budget: options
+                .frame_budget_ms
+                .map(|ms| Duration::from_millis(ms.into())),
+            render_time: None,
+            // Default to the terminal's own background, so the letterboxing
+            // blends in
+            background: options.background.or(terminal_background),
+            border_set: options.border.unwrap_or(border::THICK),
+            terminal_background,
+            protocol_zoom: options.protocol_zoom.clone(),
+            zoom_strategies: options.zoom_strategies.clone(),
+            stats_only: options.stats_only,
+            debug_overlay: false,
+            invert_errors: options.invert_errors,
+
+            // Images drawn with a graphics protocol never make it into the
+            // buffer, so snapshots show the game as ASCII art
+            ascii: snapshot.is_some(),
+            ascii_renderer: AsciiRenderer::new(
+                options.ascii_ramp.as_deref().unwrap_or(ascii::DEFAULT_RAMP),
+                options.ascii_size,
+            ),
+            ascii_frame: None,
+
+            frame_dump,
+            frame_hashes,
+            frame_stream: options.frame_stream.clone().map(FrameStream::new),
+            status_file: options.status_file.clone().map(|path| {
+                StatusFile::new(
+                    path,
+                    Duration::from_millis(
+                        options
+                            .status_interval_ms
+                            .map_or(DEFAULT_STATUS_INTERVAL_MS, u64::from),
+                    ),
+                )
+            }),
+            show_hash: options.show_hash,
+            sent_events: options
+                .show_doom_keys
+                .then(|| VecDeque::with_capacity(SENT_EVENTS_SHOWN)),
+
+            lut_enabled: lut.is_some(),
+            lut,
+            color_filter: options.color_filter,
+            scanlines: options.scanlines,
+            aspect_correction: options.aspect_correction,
+            gamma: Gamma::new(options.gamma.unwrap_or(1.0)),
+            levels: Levels::new(
+                options.brightness.unwrap_or(0.0),
+                options.contrast.unwrap_or(1.0),
+            ),
 
-    last_second: Instant,
-    frames_since_last_second: u16,
-    fps: u16,
+            started_at: Instant::now(),
+            clock: GameClock::new(),
+            turbo: false,
+            paused: options.start_paused,
+            waiting_to_start: options.start_paused,
+            render_paused: false,
+            missed_changes: false,
+            memory,
+            memory_pages: MEMORY_PAGES,
+            max_memory_pages: options.max_memory_pages.unwrap_or(MAX_MEMORY_PAGES),
+
+            seed: options.seed.unwrap_or_else(|| {
+                SystemTime::UNIX_EPOCH
+                    .elapsed()
+                    .map_or(0, |since_epoch| since_epoch.subsec_nanos())
+            }),
+            pwads,
+            pixel_format: options.pixel_format,
+            palettes,
+            palette_index: 0,
+            frame_size: DEFAULT_FRAME_SIZE,
+            screen_size: None,
+
+            death_alert: options.death_alert,
+            player_health: None,
+            death_flash_started: None,
+
+            strafe_run_tick: None,
+
+            turn_coalesce: options.turn_coalesce,
+            pending_turns: HashMap::new(),
+            turn_tick: 0,
+
+            episode_address: options.episode_address,
+            map_address: options.map_address,
+            current_map: None,
+
+            demo: None,
+            demo_tick: 0,
+            demo_dir: options
+                .demo_dir
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(".")),
+            // Demos count skills from 0
+            demo_skill: options.demo_skill.map_or(2, |skill| skill - 1),
+
+            state_dir: options
+                .state_dir
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(".")),
+            state_notice: None,
+
+            replay_buffer: options
+                .replay_mem
+                .map(|budget| ReplayBuffer::new(budget, options.replay_downscale.unwrap_or(2))),
+            replay_position: None,
+            replay_frame_at: None,
+            replay_was_paused: false,
+
+            menu_detector: options.menu_throttle.map(MenuDetector::new),
+            in_menu: false,
+
+            frame_stats: FrameStats::new(),
+
+            last_render: Instant::now(),
+            refresh_interval: options.refresh_rate.map(|hz| Duration::from_secs(1) / hz),
+            min_draw_interval: options
+                .min_draw_interval_ms
+                .map(|ms| Duration::from_millis(ms.into())),
+            low_latency: options.low_latency,
+            no_sleep: options.no_sleep,
+            max_lag: options
+                .max_ticks_behind
+                .map(|ticks| Duration::from_secs(1) * ticks / TICKS_PER_SECOND as u32),
+            dropped_ticks: 0,
+            adaptive_refresh: options.adaptive_refresh,
+            terminal_draw_time: None,
+            frames_skipped: 0,
+            draw_watchdog: options
+                .draw_timeout_ms
+                .map(|ms| DrawWatchdog::new(Duration::from_millis(ms.into()))),
+            render_thread,
+            snapshot,
+            headless,
+        }
+    }
+}
+
+/// The turn key events seen for one key during a tick.
+#[derive(Debug, Default)]
+struct PendingTurn {
+    presses: u32,
+    // Whether the last event was a release, so we know which state to
+    // leave the key in
+    released: bool,
 }
 
 /// The exported functions we call to control the game's state.
@@ -64,6 +700,8 @@ struct DoomFunctions {
     main: TypedFunction<(i32, i32), i32>,
     step: TypedFunction<(), ()>,
     add_event: TypedFunction<(i32, i32), ()>,
+    // Not exported by the bundled module, see `match_resolution`
+    set_resolution: Option<TypedFunction<(i32, i32), ()>>,
 }
 
 /// The global state of the application, including the WASM store.
@@ -71,364 +709,3353 @@ struct DoomGlobalState<'a> {
     store: &'a mut Store,
     env: &'a mut FunctionEnv<DoomApp>,
     functions: DoomFunctions,
+    input_sources: Vec<Box<dyn InputSource>>,
+    // The module file, to load again when asked
+    wasm_path: Option<PathBuf>,
+    // Quit once the game has run this many ticks
+    max_ticks: Option<u128>,
+    // Why we quit, if it wasn't the user, to say once the terminal is back
+    exit_reason: Option<String>,
 }
 
 fn main() -> Result<()> {
-    let mut stdout = std::io::stdout();
-    execute!(
-        stdout,
-        PushKeyboardEnhancementFlags(
-            KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
-                | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
-                | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
-        )
-    )
-    .context("Failed to set up keyboard input")?;
+    let options = Options::parse()?;
 
-    let terminal = ratatui::init();
-    TERMINAL.with(move |t| *t.borrow_mut() = Some(terminal));
+    if let Some(max_pages) = options.max_memory_pages {
+        if !(MEMORY_PAGES..=MAX_MEMORY_PAGES).contains(&max_pages) {
+            bail!(
+                "--max-memory-pages must be between {MEMORY_PAGES} (the initial size) \
+                and {MAX_MEMORY_PAGES}, got {max_pages}"
+            );
+        }
+    }
 
-    let mut store = Store::default();
-    let memory = Memory::new(&mut store, MemoryType::new(MEMORY_PAGES, None, false))?;
-
-    let doom_app = {
-        let picker = {
-            match Picker::from_query_stdio() {
-                Ok(picker) => picker,
-                Err(ratatui_image::errors::Errors::NoFontSize) => {
-                    // Just pick a default at random... needs to be done on Windows
-                    Picker::from_fontsize((8, 16))
-                }
-                e @ Err(_) => {
-                    // TODO: is there a better way to do this?
-                    _ = e.context("Failed to query terminal's image rendering capabilities")?;
-                    unreachable!();
-                }
-            }
-        };
+    if let Some(core) = options.pin_cpu {
+        pin_to_core(core)?;
+    }
 
-        DoomApp {
-            exit: false,
+    if options.caps {
+        return print_capabilities(options.font_size);
+    }
 
-            last_log_line: None,
-            last_log_error: false,
+    if options.list_protocols {
+        return print_protocols(options.font_size);
+    }
 
-            default_font_size: picker.font_size(),
-            image_picker: picker,
-            current_frame: None,
-            zoom: 1,
+    let wasm_bytes = match &options.wasm {
+        Some(path) => Cow::Owned(
+            fs::read(path)
+                .with_context(|| format!("Failed to read WASM module from {}", path.display()))?,
+        ),
+        None => Cow::Borrowed(WASM_BYTES),
+    };
 
-            started_at: Instant::now(),
-            memory: memory.clone(),
+    if options.list_exports {
+        return print_exports(&wasm_bytes);
+    }
 
-            last_second: Instant::now(),
-            frames_since_last_second: 0,
-            fps: 0,
-        }
+    let lut = options.lut.as_deref().map(Lut::load).transpose()?;
+
+    let key_bindings = match &options.keys {
+        Some(path) => KeyBindings::load(path)?,
+        None => KeyBindings::default(),
     };
 
-    let mut env = FunctionEnv::new(&mut store, doom_app);
-    let module = Module::new(&store, WASM_BYTES)?;
-    let imports = imports! {
-        "env" => {
-            "memory" => memory,
-        },
-        "js" => {
-            "js_console_log" => Function::new_typed_with_env(&mut store, &env, log_string_normal),
-            "js_stdout" => Function::new_typed_with_env(&mut store, &env, log_string_normal),
-            "js_stderr" => Function::new_typed_with_env(&mut store, &env, log_string_error),
-            "js_milliseconds_since_start" => Function::new_typed_with_env(&mut store, &env, milliseconds_since_start),
-            "js_draw_screen" => Function::new_typed_with_env(&mut store, &env, draw_screen),
-        },
+    // Checked up front, so a typo doesn't only show up once the game is
+    // running
+    let pwads = options
+        .pwads
+        .iter()
+        .map(|path| Pwad::load(path))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Only needed to make sense of indexed frames. Doom takes PLAYPAL from
+    // the last WAD that has one, so a PWAD's palette beats the IWAD's.
+    let palettes = if options.pixel_format == PixelFormat::Indexed {
+        Palettes::from_pwads(&pwads)
+            .or_else(|| options.palette_wad.as_deref().map(Palettes::load))
+            .transpose()?
+    } else {
+        None
     };
-    let instance = Instance::new(&mut store, &module, &imports)?;
 
-    let doom_funcs = DoomFunctions {
-        main: instance
-            .exports
-            .get_typed_function::<(i32, i32), i32>(&store, "main")
-            .context("Failed to get main function")?,
-        step: instance
-            .exports
-            .get_typed_function::<(), ()>(&store, "doom_loop_step")
-            .context("Failed to get step function")?,
-        add_event: instance
-            .exports
-            .get_typed_function::<(i32, i32), ()>(&store, "add_browser_event")
-            .context("Failed to get add event function")?,
+    let frame_dump = match &options.frame_dump {
+        Some(dir) => Some(FrameDump::new(
+            dir.clone(),
+            options.frame_dump_format,
+            options.frame_dump_limit,
+        )?),
+        None => None,
     };
 
-    let mut global_state = DoomGlobalState {
-        store: &mut store,
-        env: &mut env,
-        functions: doom_funcs,
+    let frame_hashes = if options.show_hash
+        || options.record_hashes.is_some()
+        || options.verify_hashes.is_some()
+    {
+        Some(FrameHashes::new(
+            options.record_hashes.as_deref(),
+            options.verify_hashes.as_deref(),
+        )?)
+    } else {
+        None
     };
 
-    let app_result = global_state.run();
+    // In snapshot mode, the app renders into a buffer and the terminal is
+    // never touched
+    let snapshot = options.snapshot.clone().map(|path| {
+        let (width, height) = options.snapshot_size.unwrap_or(DEFAULT_SNAPSHOT_SIZE);
+        Snapshot {
+            path,
+            area: Rect::new(0, 0, width, height),
+            ticks: options
+                .snapshot_ticks
+                .unwrap_or(DEFAULT_SNAPSHOT_TICKS)
+                .into(),
+            check: options.snapshot_check,
+        }
+    });
+    // The self-test doesn't show anything either
+    let headless = snapshot.is_some() || options.self_test;
 
-    ratatui::restore();
+    let mut stdout = std::io::stdout();
+    let render_thread = if headless {
+        None
+    } else {
+        execute!(
+            stdout,
+            PushKeyboardEnhancementFlags(
+                KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                    | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                    | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+            )
+        )
+        .context("Failed to set up keyboard input")?;
 
-    execute!(stdout, PopKeyboardEnhancementFlags)
-        .context("Failed to restore keyboard input state")?;
+        let terminal = ratatui::init();
+        if options.render_thread {
+            Some(RenderThread::new(terminal)?)
+        } else {
+            TERMINAL.with(move |t| *t.borrow_mut() = Some(terminal));
+            None
+        }
+    };
 
-    app_result
-}
+    let mut store = Store::default();
+    let memory = Memory::new(
+        &mut store,
+        MemoryType::new(MEMORY_PAGES, options.max_memory_pages, false),
+    )?;
 
-impl<'a> DoomGlobalState<'a> {
-    fn run(&mut self) -> Result<()> {
-        self.functions
-            .main
-            .call(self.store, 0, 0)
-            .context("Failed to call main function")?;
+    // With piped stdin, input is scripted instead of coming from the keyboard.
+    // The terminal queries answer on stdin too, so they're skipped.
+    let scripted_input = !io::stdin().is_terminal();
 
-        while !self.env.as_ref(self.store).exit {
-            // Poll input events, possibly updating the TUI's state
-            self.poll_events().context("failed to poll events")?;
+    let mut doom_app = {
+        let (picker, terminal_background, reports_releases) = if scripted_input || headless {
+            (
+                Picker::from_fontsize(options.font_size.unwrap_or(DEFAULT_FONT_SIZE)),
+                None,
+                false,
+            )
+        } else {
+            (
+                create_picker(options.font_size)?,
+                terminal::query_background_color(),
+                // Terminals that understand the flags pushed above report
+                // key releases
+                supports_keyboard_enhancement().unwrap_or(false),
+            )
+        };
 
-            // Now call the step function. This does nothing if the
-            // current tick isn't over.
-            self.functions
-                .step
-                .call(self.store)
-                .context("Failed to call step function")?;
+        DoomApp::new(
+            &options,
+            Resources {
+                memory,
+                picker,
+                terminal_background,
+                reports_releases,
+                scripted_input,
+                headless,
+                key_bindings,
+                lut,
+                pwads,
+                palettes,
+                frame_dump,
+                frame_hashes,
+                render_thread,
+                snapshot,
+            },
+        )
+    };
 
-            // Sleep for 1ms. No harm in a few extra calls to step,
-            // but this should help keep everything more smooth, as
-            // we'll always step within 1ms of the actual tick time.
-            thread::sleep(Duration::from_millis(1));
-        }
-        Ok(())
+    doom_app.apply_protocol_zoom();
+    doom_app.update_clock_speed();
+    // Doom ticks whenever the clock passes another 35th of a second, so
+    // running the clock faster ticks faster
+    if let Some(tick_rate) = options.tick_rate {
+        doom_app
+            .clock
+            .set_scale(tick_rate as f64 / TICKS_PER_SECOND as f64);
     }
 
-    fn poll_events(&mut self) -> Result<()> {
-        while event::poll(Duration::ZERO)? {
-            if let Event::Key(key) = event::read()? {
-                let app = self.env.as_mut(self.store);
-
-                match key.code {
-                    // We look for a few special keys, used to control the app's
-                    // behavior.
-                    KeyCode::Char('q') | KeyCode::Char('Q') => {
-                        if key.kind == KeyEventKind::Press {
-                            app.exit();
-                        }
-                    }
-
-                    KeyCode::Char('p') | KeyCode::Char('P') => {
-                        if key.kind == KeyEventKind::Press {
-                            app.cycle_protocol_type();
-                        }
-                    }
-
-                    KeyCode::Char('+') => {
-                        if key.kind == KeyEventKind::Press {
-                            app.increment_zoom();
-                        }
-                    }
+    if doom_app.pixel_format == PixelFormat::Indexed && doom_app.palettes.is_none() {
+        doom_app.log(
+            "No PLAYPAL in the PWADs or --palette-wad, showing the game in grayscale",
+            true,
+        );
+    }
 
-                    KeyCode::Char('-') => {
-                        if key.kind == KeyEventKind::Press {
-                            app.decrement_zoom();
-                        }
-                    }
+    if let Some(replay_buffer) = &doom_app.replay_buffer {
+        let seconds = replay_buffer.capacity() as f32 / TICKS_PER_SECOND as f32;
+        doom_app.log(format!("Instant replay keeps {seconds:.1} seconds"), false);
+    }
 
-                    // All other keys go to doom, subject to mapping rules in
-                    // `key_code_to_doom_key`.
-                    _ => {
-                        if let (Some(code), Some(event)) = (
-                            key_code_to_doom_key(key.code),
-                            key_event_to_doom_event(key.kind),
-                        ) {
-                            self.functions
-                                .add_event
-                                .call(self.store, event, code)
-                                .context("Failed to register input")?;
-                        }
-                    }
-                }
+    // Some terminals answer the queries above late, and the answers end up
+    // mixed into the first frame. Give them time, then throw away whatever
+    // arrived meanwhile so it isn't taken for key presses.
+    if let Some(ms) = options.startup_delay_ms {
+        stdout.flush().context("Failed to flush the terminal")?;
+        thread::sleep(Duration::from_millis(ms.into()));
+        if !scripted_input {
+            while event::poll(Duration::ZERO)? {
+                event::read()?;
             }
         }
-
-        Ok(())
     }
-}
 
-impl DoomApp {
-    fn exit(&mut self) {
-        self.exit = true;
-    }
+    let mut env = FunctionEnv::new(&mut store, doom_app);
 
-    fn cycle_protocol_type(&mut self) {
-        self.image_picker
-            .set_protocol_type(self.image_picker.protocol_type().next());
+    if options.self_test {
+        let passed = self_test(&mut store, &mut env, &wasm_bytes);
+        println!("Self-test {}", if passed { "passed" } else { "failed" });
+        std::process::exit(if passed { 0 } else { 1 });
     }
 
-    fn set_zoom(&mut self, zoom: u16) {
-        let protocol_type = self.image_picker.protocol_type();
-        let mut new_picker = ratatui_image::picker::Picker::from_fontsize((
-            self.default_font_size.0 / zoom,
-            self.default_font_size.1 / zoom,
-        ));
-        new_picker.set_protocol_type(protocol_type);
-        self.image_picker = new_picker;
-        // No need to recreate the image, display will be updated next frame anyway
-    }
+    let module = Module::new(&store, &wasm_bytes).context("Failed to load WASM module")?;
+    let doom_funcs = instantiate(&mut store, &env, &module)?;
 
-    fn increment_zoom(&mut self) {
-        self.set_zoom(self.zoom.saturating_add(1));
+    // The keyboard works alongside a script, so the game can still be quit
+    // once the script is done; crossterm reads it from the terminal rather
+    // than stdin. Without the terminal, the keyboard isn't ours, so a
+    // snapshot run only has the script, if there is one.
+    let mut input_sources: Vec<Box<dyn InputSource>> = vec![];
+    if scripted_input {
+        input_sources.push(Box::new(StdinInput::new()));
     }
-
-    fn decrement_zoom(&mut self) {
-        self.set_zoom(self.zoom.saturating_sub(1).max(1));
+    if !headless {
+        input_sources.push(Box::new(KeyboardInput::new(options.poll_budget)));
     }
-}
 
-fn log_string(mut env: FunctionEnvMut<DoomApp>, offset: i32, length: i32, error: bool) {
-    let view = env.data().memory.view(&env);
-    let slice = WasmSlice::new(&view, offset as u64, length as u64).unwrap();
-    let vec = slice.read_to_vec().unwrap();
-    // Doom itself presumably only outputs ASCII, and the rust wrapper
-    // outputs UTF-8, so it's relatively safe to unwrap here
-    let app = env.data_mut();
-    app.last_log_line = Some(String::from_utf8(vec).unwrap());
-    app.last_log_error = error;
-}
+    let mut global_state = DoomGlobalState {
+        store: &mut store,
+        env: &mut env,
+        functions: doom_funcs,
+        input_sources,
+        wasm_path: options.wasm,
+        max_ticks: options.replay_max_ticks.map(u128::from),
+        exit_reason: None,
+    };
 
-fn log_string_normal(env: FunctionEnvMut<DoomApp>, offset: i32, length: i32) {
-    log_string(env, offset, length, false);
-}
+    let app_result = global_state.run();
+    let exit_reason = global_state.exit_reason.take();
 
-fn log_string_error(env: FunctionEnvMut<DoomApp>, offset: i32, length: i32) {
-    log_string(env, offset, length, true);
-}
+    // Let the render thread finish up before the terminal is restored under
+    // it. If it stopped early, its error is the more useful one.
+    let render_result = env
+        .as_mut(&mut store)
+        .render_thread
+        .take()
+        .map_or(Ok(()), RenderThread::finish);
 
-fn milliseconds_since_start(env: FunctionEnvMut<DoomApp>) -> i32 {
-    env.data().started_at.elapsed().as_millis() as i32
-}
+    // Captured before restoring the terminal, while we can still tell its
+    // size
+    let snapshot_result = {
+        let app = env.as_ref(&store);
+        app.snapshot
+            .as_ref()
+            .map(|snapshot| snapshot.finish(&app.render_to_buffer(snapshot.area)))
+            .transpose()
+    };
 
-fn draw_screen(mut env: FunctionEnvMut<DoomApp>, offset: i32) {
-    let view = env.data().memory.view(&env);
-    let slice = WasmSlice::new(&view, offset as u64, 640 * 400 * 4).unwrap();
-    let image_data = slice.read_to_vec().unwrap();
+    let screen_text = options.screen_text.map(|path| {
+        let app = env.as_ref(&store);
+        let area = app
+            .terminal_area()
+            .unwrap_or_else(|| Rect::new(0, 0, 80, 24));
+        (path, app.render_to_string(area))
+    });
 
-    let app = env.data_mut();
-    let dynamic_image =
-        image::DynamicImage::ImageRgba8(image::RgbaImage::from_raw(640, 400, image_data).unwrap());
-    app.current_frame = Some(
-        app.image_picker
-            .new_protocol(
-                dynamic_image,
-                Rect::new(0, 0, 640, 400),
-                ratatui_image::Resize::Fit(None),
-            )
-            .unwrap(),
-    );
+    if !headless {
+        ratatui::restore();
 
-    const ONE_SECOND: Duration = Duration::from_secs(1);
-    if app.last_second.elapsed() < ONE_SECOND {
-        app.frames_since_last_second += 1;
-    } else {
-        let mut seconds = 0;
-        // In the odd case that we jumped more than one second since the last frame...
-        while app.last_second.elapsed() >= ONE_SECOND {
-            app.last_second = app.last_second.checked_add(ONE_SECOND).unwrap();
-            seconds += 1
-        }
-        app.fps = app.frames_since_last_second / seconds;
-        app.frames_since_last_second = 0;
+        execute!(stdout, PopKeyboardEnhancementFlags)
+            .context("Failed to restore keyboard input state")?;
     }
 
-    TERMINAL
-        .with(|t| {
-            t.borrow_mut()
-                .as_mut()
-                .unwrap()
-                .draw(|frame| frame.render_widget(&*app, frame.area()))
-                // Ignore the result since we can't return it due to
-                // lifetime issues, and we don't need it anyway
-                .map(|_| ())
-        })
-        .unwrap();
-}
+    if let Some(frame_dump) = &env.as_ref(&store).frame_dump {
+        println!(
+            "Wrote {} frames to {}",
+            frame_dump.written(),
+            frame_dump.dir().display()
+        );
+    }
 
-impl Widget for &DoomApp {
-    fn render(self, area: Rect, buf: &mut Buffer) {
-        let title = Line::from(vec![
-            " WASM DooM in TUI - FPS: ".bold(),
-            self.fps.to_string().bold(),
-            " - Protocol: ".bold(),
-            format!("{:?}", self.image_picker.protocol_type()).bold(),
-            " ".bold(),
-        ]);
-        let instructions = Line::from(vec![
-            " Quit ".into(),
-            "<Q>".blue().bold(),
-            " - Switch Image Protocol ".into(),
-            "<P>".blue().bold(),
-            " - Increase Zoom ".into(),
-            "<+>".blue().bold(),
-            " - Decrease Zoom ".into(),
-            "<-> ".blue().bold(),
-        ]);
-        let block = Block::bordered()
-            .title(title.centered())
-            .title_bottom(instructions.centered())
-            .border_set(border::THICK);
+    if let Some((path, text)) = screen_text {
+        fs::write(&path, text)
+            .with_context(|| format!("Failed to write screen text to {}", path.display()))?;
+    }
 
-        let log_text = self.last_log_line.as_deref().unwrap_or("").to_string();
+    if options.frame_histogram {
+        print!("{}", env.as_ref(&store).frame_stats.histogram());
+    }
 
-        let log_text = if self.last_log_error {
-            log_text.red()
-        } else {
-            log_text.yellow()
-        };
+    if let Some(frame_hashes) = &mut env.as_mut(&mut store).frame_hashes {
+        if let Some(summary) = frame_hashes.finish()? {
+            println!("{summary}");
+        }
+    }
 
-        let log_text = Text::from(log_text);
+    let exit_code = render_result.and(app_result)?;
 
-        Paragraph::new(log_text)
-            .centered()
-            .block(block)
-            .render(area, buf);
+    if let Some(difference) = snapshot_result?.flatten() {
+        eprintln!("{difference}");
+        std::process::exit(1);
+    }
 
-        // I'm not that good with ratatui, let's just do some manual math and
-        // draw over the empty part of the block
-        let image = ratatui_image::Image::new(self.current_frame.as_ref().unwrap());
-        image.render(Rect::new(2, 2, area.width - 4, area.height - 3), buf);
+    if let Some(reason) = exit_reason {
+        println!("{reason}");
     }
-}
 
-fn key_event_to_doom_event(key_event: KeyEventKind) -> Option<i32> {
-    match key_event {
-        KeyEventKind::Press => Some(0),
-        KeyEventKind::Release => Some(1),
-        KeyEventKind::Repeat => None,
+    // Doom's `main` only sets the game up, but it can still fail, e.g. if
+    // it can't find the WAD. Pass that on so scripts can tell.
+    eprintln!("Doom exited with code {exit_code}");
+    if exit_code != 0 {
+        std::process::exit(exit_code);
     }
+
+    Ok(())
 }
 
-// var keys = { KEY_ESCAPE: 27, KEY_TAB: 9 }
-fn key_code_to_doom_key(key_code: KeyCode) -> Option<i32> {
-    match key_code {
-        KeyCode::Enter => Some(13),
-        KeyCode::Backspace => Some(127),
-        KeyCode::Char(' ') => Some(32),
-        KeyCode::Left => Some(0xac),
-        KeyCode::Right => Some(0xae),
-        KeyCode::Up => Some(0xad),
-        KeyCode::Down => Some(0xaf),
-        KeyCode::Tab => Some(9),
-        KeyCode::Esc => Some(27),
+/// Checks that a module loads and runs: `--self-test`. Each check is
+/// printed as it's done, and the first one that fails ends the test, since
+/// the rest depend on it. Returns whether everything passed.
+fn self_test(store: &mut Store, env: &mut FunctionEnv<DoomApp>, wasm_bytes: &[u8]) -> bool {
+    let report = |check: &str, result: Result<()>| match result {
+        Ok(()) => {
+            println!("[x] {check}");
+            true
+        }
+        Err(e) => {
+            println!("[ ] {check}: {e:#}");
+            false
+        }
+    };
+
+    let module = match Module::new(store, wasm_bytes) {
+        Ok(module) => module,
+        Err(e) => return report("Module loads", Err(e.into())),
+    };
+    report("Module loads", Ok(()));
 
-        // Since reading individual modifiers isn't globally supported, we map
-        // z, x, c, v to ctrl, shift, alt, space in that order. Space is mapped
-        // so you can just use four nearby keys and it doesn't get too awkward.
-        KeyCode::Char('z') => Some(0x80 + 0x1d), // ctrl
-        KeyCode::Char('x') => Some(0x80 + 0x38), // alt
-        KeyCode::Char('c') => Some(16),          // shift
-        KeyCode::Char('v') => Some(32),          // space, also mapped above
+    let problems = export_problems(&module);
+    let exports = if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("{}", problems.join("; ")))
+    };
+    if !report("Required exports", exports) {
+        return false;
+    }
 
-        KeyCode::Char(ch) => Some(ch as i32),
-        KeyCode::F(f) => Some(f as i32 + 187),
+    let functions = match instantiate(store, env, &module) {
+        Ok(functions) => functions,
+        Err(e) => return report("Instantiates", Err(e)),
+    };
+    report("Instantiates", Ok(()));
 
-        _ => None,
+    // Doom keeps its framebuffer in its own memory
+    let (width, height) = DEFAULT_FRAME_SIZE;
+    let frame_size = width as u64 * height as u64 * 4;
+    let memory_size = env.as_ref(store).memory.view(store).data_size();
+    let memory = if memory_size >= frame_size {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Memory is {memory_size} bytes, a frame alone is {frame_size}"
+        ))
+    };
+    if !report("Memory has room for a framebuffer", memory) {
+        return false;
+    }
+
+    let mut global_state = DoomGlobalState {
+        store,
+        env,
+        functions,
+        input_sources: vec![],
+        wasm_path: None,
+        max_ticks: None,
+        exit_reason: None,
+    };
+    let main = global_state
+        .functions
+        .main
+        .call(global_state.store, 0, 0)
+        .context("Failed to call main function")
+        .and_then(|exit_code| match exit_code {
+            0 => Ok(()),
+            _ => Err(anyhow!("Doom exited with code {exit_code}")),
+        });
+    if !report("main runs", main) {
+        return false;
+    }
+
+    let steps = (0..SELF_TEST_TICKS).try_for_each(|_| global_state.step_one_tick());
+    if !report(&format!("{SELF_TEST_TICKS} ticks run"), steps) {
+        return false;
+    }
+
+    let frames = global_state
+        .env
+        .as_ref(global_state.store)
+        .frame_stats
+        .total_frames();
+    let drawn = if frames > 0 {
+        Ok(())
+    } else {
+        Err(anyhow!("Nothing was drawn"))
+    };
+    report("Draws frames", drawn)
+}
+
+/// Instantiates a Doom module against the app's memory and gets the
+/// functions we call. Instantiating writes the module's data segments into
+/// the memory, but leaves the rest of it alone.
+fn instantiate(
+    store: &mut Store,
+    env: &FunctionEnv<DoomApp>,
+    module: &Module,
+) -> Result<DoomFunctions> {
+    let problems = export_problems(module);
+    if !problems.is_empty() {
+        bail!(
+            "The WASM module doesn't have the exports Doom needs:\n{}",
+            problems.join("\n")
+        );
+    }
+
+    // The bundled module doesn't load PWADs, and would quietly go without
+    let app = env.as_mut(store);
+    if !app.pwads.is_empty()
+        && !module
+            .imports()
+            .any(|import| import.name() == "js_pwad_read")
+    {
+        app.log(
+            "This WASM module can't load PWADs, so --pwad does nothing; \
+             it needs to import js_pwad_read",
+            true,
+        );
+    }
+
+    let memory = env.as_ref(store).memory.clone();
+    let imports = imports! {
+        "env" => {
+            "memory" => memory,
+        },
+        "js" => {
+            "js_console_log" => Function::new_typed_with_env(store, env, log_string_normal),
+            "js_stdout" => Function::new_typed_with_env(store, env, log_string_normal),
+            "js_stderr" => Function::new_typed_with_env(store, env, log_string_error),
+            "js_milliseconds_since_start" => Function::new_typed_with_env(store, env, milliseconds_since_start),
+            "js_draw_screen" => Function::new_typed_with_env(store, env, draw_screen),
+            // Not imported by the bundled module, see `draw_screen_region`
+            "js_draw_screen_region" => Function::new_typed_with_env(store, env, draw_screen_region),
+            // Not imported by the bundled module, see `random_seed`
+            "js_random_seed" => Function::new_typed_with_env(store, env, random_seed),
+            // Not imported by the bundled module, see `set_palette`
+            "js_set_palette" => Function::new_typed_with_env(store, env, set_palette),
+            // Not imported by the bundled module, see `pwad_read`
+            "js_pwad_count" => Function::new_typed_with_env(store, env, pwad_count),
+            "js_pwad_size" => Function::new_typed_with_env(store, env, pwad_size),
+            "js_pwad_read" => Function::new_typed_with_env(store, env, pwad_read),
+        },
+    };
+    let instance =
+        Instance::new(store, module, &imports).context("Failed to instantiate WASM module")?;
+
+    // Not exported by the bundled module, which always draws at 640x400
+    let screen_size = instance
+        .exports
+        .get_typed_function::<(), i32>(store, "doom_screen_width")
+        .ok()
+        .zip(
+            instance
+                .exports
+                .get_typed_function::<(), i32>(store, "doom_screen_height")
+                .ok(),
+        );
+    env.as_mut(store).screen_size = screen_size;
+
+    Ok(DoomFunctions {
+        main: instance
+            .exports
+            .get_typed_function::<(i32, i32), i32>(store, "main")
+            .context("Failed to get main function")?,
+        step: instance
+            .exports
+            .get_typed_function::<(), ()>(store, "doom_loop_step")
+            .context("Failed to get step function")?,
+        add_event: instance
+            .exports
+            .get_typed_function::<(i32, i32), ()>(store, "add_browser_event")
+            .context("Failed to get add event function")?,
+        set_resolution: instance
+            .exports
+            .get_typed_function::<(i32, i32), ()>(store, "doom_set_resolution")
+            .ok(),
+    })
+}
+
+/// Asks the terminal what it supports. With `font_size`, the protocol is
+/// still detected, but whatever font size the terminal reports is ignored.
+fn create_picker(font_size: Option<FontSize>) -> Result<Picker> {
+    let picker = match Picker::from_query_stdio() {
+        // Some terminals answer with nonsense, and a zero font size breaks
+        // all our image size math, so treat that like no answer at all
+        Ok(picker) if picker.font_size().0 == 0 || picker.font_size().1 == 0 => {
+            let mut fallback = Picker::from_fontsize(DEFAULT_FONT_SIZE);
+            fallback.set_protocol_type(picker.protocol_type());
+            Ok(fallback)
+        }
+        Ok(picker) => Ok(picker),
+        Err(ratatui_image::errors::Errors::NoFontSize) => {
+            // Just pick a default at random... needs to be done on Windows
+            Ok(Picker::from_fontsize(DEFAULT_FONT_SIZE))
+        }
+        e @ Err(_) => e.context("Failed to query terminal's image rendering capabilities"),
+    }?;
+
+    Ok(match font_size {
+        Some(font_size) => {
+            let mut overridden = Picker::from_fontsize(font_size);
+            overridden.set_protocol_type(picker.protocol_type());
+            overridden
+        }
+        None => picker,
+    })
+}
+
+/// Prints what we could find out about the terminal, for `--caps`.
+fn print_capabilities(font_size: Option<FontSize>) -> Result<()> {
+    enable_raw_mode().context("Failed to enable raw mode")?;
+    let picker = create_picker(font_size);
+    let background = terminal::query_background_color();
+    disable_raw_mode().context("Failed to disable raw mode")?;
+
+    let picker = picker?;
+    println!("Image protocol: {:?}", picker.protocol_type());
+    println!(
+        "Font size: {}x{}{}",
+        picker.font_size().0,
+        picker.font_size().1,
+        if font_size.is_some() {
+            " (from --font-size)"
+        } else {
+            ""
+        }
+    );
+    match background {
+        Some(color) => println!(
+            "Background color: {color} ({})",
+            if terminal::is_light(color) {
+                "light"
+            } else {
+                "dark"
+            }
+        ),
+        None => println!("Background color: unknown"),
+    }
+
+    Ok(())
+}
+
+/// Prints which image protocols work, for `--list-protocols`: the terminal
+/// has to say it supports them, and a test image has to encode and render
+/// with them on our end, which catches e.g. font sizes they can't use.
+/// iTerm2's protocol can't be asked about, so it's only marked as maybe
+/// working.
+fn print_protocols(font_size: Option<FontSize>) -> Result<()> {
+    enable_raw_mode().context("Failed to enable raw mode")?;
+    let picker = create_picker(font_size);
+    let graphics = terminal::query_graphics_support();
+    disable_raw_mode().context("Failed to disable raw mode")?;
+
+    let mut picker = picker?;
+    let detected = picker.protocol_type();
+    let test_image = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+        16,
+        16,
+        image::Rgba([255, 0, 0, 255]),
+    ));
+    let area = Rect::new(0, 0, 4, 2);
+
+    for protocol_type in [
+        ProtocolType::Halfblocks,
+        ProtocolType::Sixel,
+        ProtocolType::Kitty,
+        ProtocolType::Iterm2,
+    ] {
+        picker.set_protocol_type(protocol_type);
+        let result = picker
+            .new_protocol(test_image.clone(), area, ratatui_image::Resize::Fit(None))
+            .map(|protocol| {
+                let mut buffer = Buffer::empty(area);
+                ratatui_image::Image::new(&protocol).render(area, &mut buffer);
+            });
+
+        let detected = if protocol_type == detected {
+            " (detected)"
+        } else {
+            ""
+        };
+        let supported = match protocol_type {
+            ProtocolType::Halfblocks => Some(true),
+            ProtocolType::Sixel => graphics.map(|graphics| graphics.sixel),
+            ProtocolType::Kitty => graphics.map(|graphics| graphics.kitty),
+            ProtocolType::Iterm2 => None,
+        };
+        match (result, supported) {
+            (Err(e), _) => println!("[ ] {protocol_type:?}{detected}: {e}"),
+            (Ok(()), Some(true)) => println!("[x] {protocol_type:?}{detected}"),
+            (Ok(()), Some(false)) => {
+                println!("[ ] {protocol_type:?}{detected}: the terminal doesn't support it")
+            }
+            (Ok(()), None) => {
+                println!("[?] {protocol_type:?}{detected}: the terminal didn't say")
+            }
+        }
+    }
+    println!("[x] ASCII");
+
+    Ok(())
+}
+
+/// Prints the module's exported functions, for `--list-exports`.
+fn print_exports(wasm_bytes: &[u8]) -> Result<()> {
+    let store = Store::default();
+    let module = Module::new(&store, wasm_bytes).context("Failed to load WASM module")?;
+
+    for export in module.exports() {
+        if let ExternType::Function(ty) = export.ty() {
+            println!("{}: {ty}", export.name());
+        }
+    }
+
+    for problem in export_problems(&module) {
+        println!("{problem}");
+    }
+
+    Ok(())
+}
+
+/// The functions Doom modules need to export, with their parameter and
+/// result types.
+const REQUIRED_EXPORTS: &[(&str, &[Type], &[Type])] = &[
+    ("main", &[Type::I32, Type::I32], &[Type::I32]),
+    ("doom_loop_step", &[], &[]),
+    ("add_browser_event", &[Type::I32, Type::I32], &[]),
+];
+
+/// What's wrong with a module's exports, if anything: required functions
+/// that are missing or have the wrong signature. Checked before
+/// instantiating, since getting a typed function only says that the types
+/// didn't match, not how.
+fn export_problems(module: &Module) -> Vec<String> {
+    REQUIRED_EXPORTS
+        .iter()
+        .filter_map(|&(name, params, results)| {
+            let expected = FunctionType::new(params, results);
+            let export = module.exports().find(|export| export.name() == name);
+            match export.as_ref().map(|export| export.ty()) {
+                None => Some(format!(
+                    "Missing required export: {name}, which should be {expected}"
+                )),
+                Some(ExternType::Function(actual)) if *actual == expected => None,
+                Some(ExternType::Function(actual)) => Some(format!(
+                    "Export {name} is {actual}, but should be {expected}"
+                )),
+                Some(other) => Some(format!(
+                    "Export {name} is {other:?}, but should be a function: {expected}"
+                )),
+            }
+        })
+        .collect()
+}
+
+/// Pins the current (and only) thread to a core. If the platform doesn't
+/// support it, we just carry on unpinned.
+#[cfg(feature = "affinity")]
+fn pin_to_core(core: usize) -> Result<()> {
+    let core_ids = core_affinity::get_core_ids().unwrap_or_default();
+    // Platforms without affinity support don't list any cores
+    if core_ids.is_empty() {
+        eprintln!("Can't pin to CPU cores on this platform, continuing without pinning");
+        return Ok(());
+    }
+    let Some(core_id) = core_ids.into_iter().find(|id| id.id == core) else {
+        bail!("--pin-cpu: there's no CPU core {core}");
+    };
+    if !core_affinity::set_for_current(core_id) {
+        eprintln!("Failed to pin to CPU core {core}, continuing without pinning");
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "affinity"))]
+fn pin_to_core(_core: usize) -> Result<()> {
+    bail!("--pin-cpu needs the `affinity` feature, rebuild with `--features affinity`");
+}
+
+impl<'a> DoomGlobalState<'a> {
+    /// Runs the game until the user quits, and returns what Doom's `main`
+    /// returned.
+    fn run(&mut self) -> Result<i32> {
+        let exit_code = self
+            .functions
+            .main
+            .call(self.store, 0, 0)
+            .context("Failed to call main function")?;
+
+        if self.env.as_ref(self.store).match_resolution {
+            if self.functions.set_resolution.is_none() {
+                self.env.as_mut(self.store).log(
+                    "This build can't change resolution, it doesn't export doom_set_resolution",
+                    true,
+                );
+            } else if let Some(area) = self.env.as_ref(self.store).terminal_area() {
+                self.match_resolution(area)?;
+            }
+        }
+
+        // So there's something on screen behind the prompt
+        if self.env.as_ref(self.store).waiting_to_start {
+            self.step_one_tick()?;
+        }
+
+        let mut last_iteration = Instant::now();
+        while !self.env.as_ref(self.store).exit {
+            // If the process was suspended (e.g. with `kill -STOP`, or Ctrl-Z
+            // outside of raw mode) or the whole machine slept, a lot of time
+            // passed in one go, and Doom would run every tick it missed as
+            // fast as it can. Rather than handling signals, which isn't
+            // portable, we notice the gap itself and keep it off the game
+            // clock, so the game picks up where it was.
+            let gap = last_iteration.elapsed();
+            last_iteration = Instant::now();
+            if gap >= SUSPEND_GAP {
+                self.env.as_mut(self.store).clock.skip(gap);
+            }
+
+            // Poll input events, possibly updating the TUI's state
+            self.poll_events().context("failed to poll events")?;
+            self.flush_turns()?;
+
+            // Now call the step function. This does nothing if the
+            // current tick isn't over. There's no need while paused, since
+            // the clock isn't moving.
+            if !self.env.as_ref(self.store).paused {
+                self.env.as_mut(self.store).limit_lag();
+                self.functions
+                    .step
+                    .call(self.store)
+                    .context("Failed to call step function")?;
+            }
+
+            self.release_stale_keys()?;
+
+            let pages = self.env.as_ref(self.store).memory.view(self.store).size().0;
+            let app = self.env.as_mut(self.store);
+            app.memory_pages = pages;
+            app.write_status();
+
+            if app
+                .snapshot
+                .as_ref()
+                .is_some_and(|snapshot| app.game_tick() >= snapshot.ticks)
+            {
+                app.exit = true;
+            }
+
+            let tick = app.game_tick();
+            if self.max_ticks.is_some_and(|max_ticks| tick >= max_ticks) {
+                let reason =
+                    format!("Quitting after {tick} ticks, the most --replay-max-ticks allows");
+                app.log(reason.clone(), false);
+                app.exit = true;
+                self.exit_reason = Some(reason);
+            }
+
+            // Demos have one entry per tick, with the keys held during it
+            let app = self.env.as_mut(self.store);
+            let tick = app.game_tick();
+            if let Some(demo) = &mut app.demo {
+                while app.demo_tick < tick {
+                    demo.record_tic();
+                    app.demo_tick += 1;
+                }
+            }
+
+            // Keep the strafe-run keys held every tick while the assist is
+            // on, so nothing in the game can drop them
+            let app = self.env.as_mut(self.store);
+            if let Some(last_tick) = app.strafe_run_tick {
+                let tick = app.game_tick();
+                if tick != last_tick {
+                    app.strafe_run_tick = Some(tick);
+                    self.send_strafe_run(0)?;
+                }
+            }
+
+            // Doom only draws when it has a new frame. If we want to refresh
+            // more often than that, re-render the last frame ourselves.
+            let app = self.env.as_mut(self.store);
+            if app
+                .refresh_interval
+                .is_some_and(|interval| app.last_render.elapsed() >= interval)
+            {
+                if let Err(e) = app.render() {
+                    app.recover_or_panic("Failed to refresh the screen", e);
+                }
+            }
+
+            if let Err(e) = app.advance_replay() {
+                app.recover_or_panic("Failed to show replay", e);
+            }
+
+            // Sleep for 1ms. No harm in a few extra calls to step,
+            // but this should help keep everything more smooth, as
+            // we'll always step within 1ms of the actual tick time.
+            // In turbo mode, ticks come so fast we don't want to wait.
+            // In menus, nothing much happens, so we can wait longer.
+            // With --low-latency, we wait for input instead, waking up as
+            // soon as a key is pressed or the next tick is due.
+            // With --no-sleep, we don't wait at all and step as fast as we
+            // can.
+            let app = self.env.as_ref(self.store);
+            if app.no_sleep {
+                continue;
+            } else if app.low_latency {
+                let mut timeout = app
+                    .clock
+                    .real_time_until(app.next_tick_time())
+                    .map_or(MAX_INPUT_WAIT, |until| until.min(MAX_INPUT_WAIT));
+                if let Some(interval) = app.refresh_interval {
+                    timeout = timeout.min(interval.saturating_sub(app.last_render.elapsed()));
+                }
+                if let Some(source) = self.input_sources.first_mut() {
+                    source.wait(timeout)?;
+                }
+            } else if app.in_menu {
+                thread::sleep(MENU_SLEEP);
+            } else if !app.turbo {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+        Ok(exit_code)
+    }
+
+    /// Asks Doom to draw at the resolution that fills a terminal of this
+    /// size pixel for pixel, for builds that export
+    /// `doom_set_resolution(width, height)`. The build says which
+    /// resolution it went with through `doom_screen_width` and
+    /// `doom_screen_height`, so it's free to pick the closest one it can do.
+    fn match_resolution(&mut self, area: Rect) -> Result<()> {
+        let app = self.env.as_ref(self.store);
+        if !app.match_resolution {
+            return Ok(());
+        }
+        let Some(set_resolution) = &self.functions.set_resolution else {
+            return Ok(());
+        };
+        let (width, height) = app.matching_resolution(area);
+        if (width, height) == app.frame_size {
+            return Ok(());
+        }
+        set_resolution
+            .call(self.store, width as i32, height as i32)
+            .context("Failed to call doom_set_resolution")
+    }
+
+    fn poll_events(&mut self) -> Result<()> {
+        let mut events = vec![];
+        for source in &mut self.input_sources {
+            events.extend(source.poll()?);
+        }
+
+        for event in events {
+            match event {
+                InputEvent::Key(key) => self.handle_key(key)?,
+                InputEvent::Doom { kind, code } => self.send_to_doom(kind, code)?,
+                InputEvent::Resize { width, height } => {
+                    self.match_resolution(Rect::new(0, 0, width, height))?
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Result<()> {
+        let app = self.env.as_mut(self.store);
+
+        // Ctrl-C and a double Esc always quit, no matter how the other
+        // keys are set up, so there's always a way out.
+        if key.kind == KeyEventKind::Press {
+            if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                app.exit();
+                return Ok(());
+            }
+
+            // Backing out of nested menus is a double Esc too, so that one
+            // asks first. Y quits, and any other key carries on as usual.
+            if app.confirming_quit {
+                app.confirming_quit = false;
+                if matches!(key.code, KeyCode::Char('y' | 'Y')) {
+                    app.exit();
+                    return Ok(());
+                }
+                app.log("Not quitting", false);
+            }
+
+            // Ctrl-R reloads the key bindings file, if there is one
+            if key.code == KeyCode::Char('r') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                app.reload_key_bindings();
+                return Ok(());
+            }
+        }
+
+        // Any other key starts the game, and does nothing else. Modifiers on
+        // their own don't count, since they're often pressed on the way to
+        // a shortcut, e.g. for the capture software.
+        if app.waiting_to_start
+            && key.kind == KeyEventKind::Press
+            && !matches!(key.code, KeyCode::Modifier(_))
+        {
+            app.waiting_to_start = false;
+            app.toggle_pause();
+            return Ok(());
+        }
+
+        // While an overlay or the console is open, it gets every key press
+        // and repeat, so nothing leaks through to Doom; only the two keys
+        // above come first. Releases still go through, so keys held when it
+        // opened don't get stuck.
+        if app.console.is_some() {
+            if key.kind != KeyEventKind::Release {
+                return self.console_key(key.code);
+            }
+        } else if app.overlay.is_some() {
+            if key.kind != KeyEventKind::Release {
+                app.overlay_key(key.code);
+                return Ok(());
+            }
+        } else if key.kind == KeyEventKind::Press && key.code == KeyCode::Esc {
+            if app
+                .last_escape_press
+                .is_some_and(|at| at.elapsed() < DOUBLE_ESCAPE_TIMEOUT)
+            {
+                app.last_escape_press = None;
+                app.confirming_quit = true;
+                app.log(
+                    "Quit? Press Y to quit, or any other key to keep playing",
+                    false,
+                );
+            } else {
+                app.last_escape_press = Some(Instant::now());
+            }
+
+            if app.key_bindings.esc_is_for_app() {
+                if app.photo_mode {
+                    self.toggle_photo_mode()?;
+                } else if app.replay_position.is_some() {
+                    app.stop_replay();
+                }
+                return Ok(());
+            }
+        }
+
+        if app.photo_mode && key.code == KeyCode::Enter {
+            if key.kind == KeyEventKind::Press {
+                app.take_photo();
+            }
+            return Ok(());
+        }
+
+        let photo_binding = if app.photo_mode {
+            photo_mode_binding(key.code)
+        } else {
+            None
+        };
+        let binding = match photo_binding.or_else(|| app.key_bindings.get(key.code)) {
+            Some(Binding::App(AppAction::StepTick)) if !app.paused => {
+                app.key_bindings.doom_key(key.code).map(Binding::Doom)
+            }
+            // Doom can use the key too (e.g. when naming a save), so it's
+            // only taken when there's a zoom to reset
+            Some(Binding::App(AppAction::ResetZoom)) if app.zoom == 1 => {
+                app.key_bindings.doom_key(key.code).map(Binding::Doom)
+            }
+            binding => binding,
+        };
+
+        // The key stays held in Doom until it goes stale instead
+        if key.kind == KeyEventKind::Release
+            && !app.forward_releases
+            && matches!(binding, Some(Binding::Doom(_)))
+        {
+            return Ok(());
+        }
+
+        match binding {
+            // Stepping needs to call into Doom, so it's handled here rather
+            // than in `perform_action`
+            Some(Binding::App(AppAction::StepTick)) => {
+                if key.kind == KeyEventKind::Press {
+                    self.step_one_tick()?;
+                }
+            }
+            Some(Binding::App(AppAction::TogglePhotoMode)) => {
+                if key.kind == KeyEventKind::Press {
+                    self.toggle_photo_mode()?;
+                }
+            }
+            // Shift restarts the game in the new module instead
+            Some(Binding::App(AppAction::ReloadModule)) => {
+                if key.kind == KeyEventKind::Press {
+                    let reset = key.modifiers.contains(KeyModifiers::SHIFT);
+                    if let Err(e) = self.reload_module(reset) {
+                        self.env.as_mut(self.store).log(format!("{e:#}"), true);
+                    }
+                }
+            }
+            // Shift loads from the slot instead
+            Some(Binding::App(AppAction::SaveState(slot))) => {
+                if key.kind == KeyEventKind::Press {
+                    let result = if key.modifiers.contains(KeyModifiers::SHIFT) {
+                        self.load_state(slot)
+                    } else {
+                        self.save_state(slot)
+                    };
+                    if let Err(e) = result {
+                        self.env.as_mut(self.store).log(format!("{e:#}"), true);
+                    }
+                }
+            }
+            Some(Binding::App(AppAction::StrafeRun)) => match key.kind {
+                KeyEventKind::Press => {
+                    app.strafe_run_tick = Some(app.game_tick());
+                    self.send_strafe_run(0)?;
+                }
+                KeyEventKind::Release => {
+                    app.strafe_run_tick = None;
+                    self.send_strafe_run(1)?;
+                }
+                KeyEventKind::Repeat => {}
+            },
+
+            // Some keys are bound to actions that control the app's
+            // behavior.
+            Some(Binding::App(action)) => {
+                if key.kind == KeyEventKind::Press {
+                    app.perform_action(action);
+                }
+            }
+
+            // All other keys go to doom, either as bound or subject to
+            // the default mapping rules in `KeyBindings::doom_key`.
+            Some(Binding::Doom(code))
+                if app.turn_coalesce.is_some() && TURN_KEYS.contains(&code) =>
+            {
+                if let Some(kind) = key_event_to_doom_event(key.kind) {
+                    let pending = app.pending_turns.entry(code).or_default();
+                    if kind == 0 {
+                        pending.presses += 1;
+                    }
+                    pending.released = kind == 1;
+                }
+            }
+            Some(Binding::Doom(code)) => {
+                if key.kind == KeyEventKind::Release {
+                    app.held_keys.remove(&code);
+                } else {
+                    app.held_keys.insert(code, Instant::now());
+                }
+                if let Some(kind) = key_event_to_doom_event(key.kind) {
+                    self.send_to_doom(kind, code)?;
+                }
+            }
+
+            None => {
+                if app.log_unknown_keys && key.kind == KeyEventKind::Press {
+                    let key_name = if key.modifiers.is_empty() {
+                        format!("{:?}", key.code)
+                    } else {
+                        format!("{:?} with {:?}", key.code, key.modifiers)
+                    };
+                    app.log(format!("Key does nothing: {key_name}"), false);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles a key press while the console is open. Enter types the line
+    /// into Doom, one key press and release per character.
+    fn console_key(&mut self, code: KeyCode) -> Result<()> {
+        let app = self.env.as_mut(self.store);
+        let Some(line) = &mut app.console else {
+            return Ok(());
+        };
+        match code {
+            KeyCode::Esc => app.console = None,
+            KeyCode::Backspace => {
+                line.pop();
+            }
+            KeyCode::Char(ch) if ch.is_ascii_graphic() || ch == ' ' => {
+                if line.len() < MAX_CONSOLE_LENGTH {
+                    line.push(ch);
+                }
+            }
+            KeyCode::Enter => {
+                let line = app.console.take().unwrap_or_default();
+                self.type_into_doom(&line)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Types text into Doom, one key press and release per character, e.g.
+    /// for cheats. Sent as the characters themselves rather than through
+    /// the key bindings, which would turn e.g. the c in idclip into shift.
+    /// Cheats are matched in lowercase.
+    fn type_into_doom(&mut self, text: &str) -> Result<()> {
+        for ch in text.bytes() {
+            let code = ch.to_ascii_lowercase() as i32;
+            self.send_to_doom(0, code)?;
+            self.send_to_doom(1, code)?;
+        }
+        Ok(())
+    }
+
+    /// Turns photo mode on or off. Doom has no free camera, so this turns
+    /// on no clipping with the idclip cheat, to fly through walls, and
+    /// switches the movement keys to WASD.
+    fn toggle_photo_mode(&mut self) -> Result<()> {
+        // Keys held now were pressed under the other layout, and would
+        // never get released otherwise
+        let held: Vec<_> = self.env.as_mut(self.store).held_keys.drain().collect();
+        for (code, _) in held {
+            self.send_to_doom(1, code)?;
+        }
+        self.type_into_doom("idclip")?;
+
+        let app = self.env.as_mut(self.store);
+        app.photo_mode = !app.photo_mode;
+        if app.photo_mode {
+            app.log(
+                "Photo mode: WASD to move, Q and E to turn, Enter to take a photo",
+                false,
+            );
+        } else {
+            app.photo_frame = None;
+            app.log("Photo mode off", false);
+        }
+        Ok(())
+    }
+
+    /// Loads the module from `--wasm` again and swaps it in, so a new build
+    /// can be tried without restarting. The game keeps going with the memory
+    /// it had, which only works if the build's memory layout didn't change;
+    /// with `reset`, the game starts over in fresh memory instead. If the new
+    /// module can't be loaded, the old one keeps running.
+    ///
+    /// Instantiating writes the module's data segments into memory, over the
+    /// game's state, so the memory is copied first and put back afterwards,
+    /// whether or not the new module loaded.
+    fn reload_module(&mut self, reset: bool) -> Result<()> {
+        let Some(path) = &self.wasm_path else {
+            bail!("No WASM module to reload, pass one with --wasm");
+        };
+        let wasm_bytes = fs::read(path)
+            .with_context(|| format!("Failed to read WASM module from {}", path.display()))?;
+
+        let old_memory = self.env.as_ref(self.store).memory.clone();
+        let saved_memory = if reset {
+            let memory_type = old_memory.ty(self.store);
+            let memory =
+                Memory::new(self.store, memory_type).context("Failed to create WASM memory")?;
+            self.env.as_mut(self.store).memory = memory;
+            None
+        } else {
+            Some(
+                old_memory
+                    .view(self.store)
+                    .copy_to_vec()
+                    .context("Failed to read WASM memory")?,
+            )
+        };
+
+        let result = Module::new(self.store, &wasm_bytes)
+            .context("Failed to load WASM module")
+            .and_then(|module| instantiate(self.store, self.env, &module));
+        if let Some(saved_memory) = saved_memory {
+            old_memory
+                .view(self.store)
+                .write(0, &saved_memory)
+                .context("Failed to restore WASM memory")?;
+        }
+        let functions = match result {
+            Ok(functions) => functions,
+            Err(e) => {
+                self.env.as_mut(self.store).memory = old_memory;
+                return Err(e);
+            }
+        };
+        self.functions = functions;
+
+        if reset {
+            self.env
+                .as_mut(self.store)
+                .clock
+                .set_elapsed(Duration::ZERO);
+            let exit_code = self
+                .functions
+                .main
+                .call(self.store, 0, 0)
+                .context("Failed to call main function")?;
+            if exit_code != 0 {
+                bail!("Doom exited with code {exit_code} after reloading");
+            }
+        }
+
+        let app = self.env.as_mut(self.store);
+        let message = format!(
+            "Reloaded {}{}",
+            path.display(),
+            if reset { " and restarted" } else { "" }
+        );
+        app.log(message, false);
+        Ok(())
+    }
+
+    /// Runs exactly one game tick, for going through a paused game frame by
+    /// frame.
+    fn step_one_tick(&mut self) -> Result<()> {
+        self.env.as_mut(self.store).advance_clock_one_tick();
+        self.functions
+            .step
+            .call(self.store)
+            .context("Failed to call step function")
+    }
+
+    /// Saves the game to a slot. This only happens between calls into Doom,
+    /// so everything Doom needs is in its memory.
+    fn save_state(&mut self, slot: u8) -> Result<()> {
+        let app = self.env.as_ref(self.store);
+        let path = SaveState::path(&app.state_dir, slot);
+        let state = SaveState {
+            game_time: app.clock.elapsed(),
+            saved_at: SystemTime::now(),
+            memory: app
+                .memory
+                .view(self.store)
+                .copy_to_vec()
+                .context("Failed to read WASM memory")?,
+        };
+        state.save(&path)?;
+
+        let notice = format!("Saved slot {slot} ({})", state.describe());
+        self.env.as_mut(self.store).state_notice = Some((notice, Instant::now()));
+        Ok(())
+    }
+
+    fn load_state(&mut self, slot: u8) -> Result<()> {
+        let app = self.env.as_ref(self.store);
+        let path = SaveState::path(&app.state_dir, slot);
+        let memory = app.memory.clone();
+        let state = SaveState::load(&path)?;
+
+        // The memory may have grown after the save, but never shrinks. Any
+        // memory past the saved part is left as is; Doom's allocator won't
+        // know about it after loading.
+        let size = memory.view(self.store).data_size() as usize;
+        if state.memory.len() > size {
+            let pages = (state.memory.len() - size).div_ceil(WASM_PAGE_SIZE);
+            memory
+                .grow(self.store, pages as u32)
+                .context("Failed to grow WASM memory to fit the save state")?;
+        }
+        memory
+            .view(self.store)
+            .write(0, &state.memory)
+            .context("Failed to write WASM memory")?;
+
+        let app = self.env.as_mut(self.store);
+        // Otherwise, Doom would run all the ticks between the save and now
+        app.clock.set_elapsed(state.game_time);
+        app.state_notice = Some((
+            format!("Loaded slot {slot} ({})", state.describe()),
+            Instant::now(),
+        ));
+        Ok(())
+    }
+
+    fn send_to_doom(&mut self, kind: i32, code: i32) -> Result<()> {
+        let app = self.env.as_mut(self.store);
+        if let Some(demo) = &mut app.demo {
+            demo.key_event(kind, code);
+        }
+        if let Some(sent_events) = &mut app.sent_events {
+            if sent_events.len() == SENT_EVENTS_SHOWN {
+                sent_events.pop_front();
+            }
+            sent_events.push_back((kind, code));
+        }
+        self.functions
+            .add_event
+            .call(self.store, kind, code)
+            .context("Failed to register input")
+    }
+
+    /// Forwards the turn events held back during the last tick, once it's
+    /// over, with the number of presses capped.
+    fn flush_turns(&mut self) -> Result<()> {
+        let app = self.env.as_mut(self.store);
+        let Some(max_presses) = app.turn_coalesce else {
+            return Ok(());
+        };
+        let tick = app.game_tick();
+        if tick == app.turn_tick {
+            return Ok(());
+        }
+        app.turn_tick = tick;
+
+        let pending_turns = std::mem::take(&mut app.pending_turns);
+        for (code, pending) in pending_turns {
+            for _ in 0..pending.presses.min(max_presses) {
+                self.send_to_doom(0, code)?;
+            }
+            if pending.released {
+                self.send_to_doom(1, code)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Releases keys we haven't heard from in a while, since not all
+    /// terminals report releases and Doom would otherwise think they're
+    /// stuck down.
+    fn release_stale_keys(&mut self) -> Result<()> {
+        let app = self.env.as_mut(self.store);
+        // Without releases, this is the only way keys are let go of, so it
+        // can't be off
+        let timeout = match app.key_release_timeout {
+            Some(timeout) => timeout,
+            None if !app.forward_releases => DEFAULT_KEY_RELEASE_TIMEOUT,
+            None => return Ok(()),
+        };
+
+        let mut stale = vec![];
+        app.held_keys.retain(|code, last_event| {
+            let held = last_event.elapsed() < timeout;
+            if !held {
+                stale.push(*code);
+            }
+            held
+        });
+
+        for code in stale {
+            self.send_to_doom(1, code)?;
+        }
+        Ok(())
+    }
+
+    fn send_strafe_run(&mut self, kind: i32) -> Result<()> {
+        for code in STRAFE_RUN_KEYS {
+            self.send_to_doom(kind, code)?;
+        }
+        Ok(())
+    }
+}
+
+impl DoomApp {
+    fn exit(&mut self) {
+        self.exit = true;
+    }
+
+    fn fps(&self) -> u16 {
+        self.frame_stats.fps()
+    }
+
+    /// Whether the game is drawing fewer frames than it should. Doom doesn't
+    /// draw while paused and we throttle menus, so those don't count, and
+    /// neither does 0, which is what we show before the first second is up.
+    fn fps_is_low(&self) -> bool {
+        let fps = self.fps();
+        self.low_fps.is_some_and(|low_fps| fps > 0 && fps < low_fps)
+            && !self.paused
+            && !self.in_menu
+    }
+
+    /// How long each of the most recent frames took, oldest first.
+    fn frame_times(&self) -> impl ExactSizeIterator<Item = Duration> + '_ {
+        self.frame_stats.frame_times()
+    }
+
+    fn game_tick(&self) -> u128 {
+        self.clock.elapsed().as_millis() * TICKS_PER_SECOND / 1000
+    }
+
+    fn log(&mut self, line: impl Into<String>, error: bool) {
+        let line = line.into();
+        if self.log_history.len() == LOG_HISTORY_LINES {
+            self.log_history.pop_front();
+        }
+        self.log_history.push_back((line.clone(), error));
+
+        self.last_log_line = Some(line);
+        self.last_log_error = error;
+        self.last_log_at = Instant::now();
+        self.pending_log_line = None;
+    }
+
+    /// Keeps a line Doom logged until the next render. Errors take priority
+    /// over normal lines logged after them in the same frame.
+    fn queue_log(&mut self, line: Vec<u8>, error: bool) {
+        if !error
+            && self
+                .pending_log_line
+                .as_ref()
+                .is_some_and(|(_, error)| *error)
+        {
+            return;
+        }
+        self.pending_log_line = Some((line, error));
+    }
+
+    fn flush_log(&mut self) {
+        let Some((line, error)) = self.pending_log_line.take() else {
+            return;
+        };
+        // Doom itself presumably only outputs ASCII, and the rust wrapper
+        // outputs UTF-8, but there's no reason to crash if something else
+        // slips through
+        self.log(String::from_utf8_lossy(&line), error);
+    }
+
+    fn on_player_death(&mut self) {
+        // The bell isn't something ratatui knows about, so it goes straight
+        // to the terminal
+        let mut stdout = std::io::stdout();
+        _ = stdout.write_all(b"\x07").and_then(|_| stdout.flush());
+        self.death_flash_started = Some(Instant::now());
+    }
+
+    fn perform_action(&mut self, action: AppAction) {
+        match action {
+            AppAction::Quit => self.exit(),
+            AppAction::CycleProtocol => self.cycle_protocol_type(),
+            AppAction::ZoomIn => self.increment_zoom(),
+            AppAction::ZoomOut => self.decrement_zoom(),
+            AppAction::ResetZoom => {
+                self.set_zoom(1);
+                self.log("Zoom reset", false);
+            }
+            AppAction::Turbo => self.toggle_turbo(),
+            AppAction::ToggleLut => self.toggle_lut(),
+            AppAction::CycleColorFilter => self.cycle_color_filter(),
+            AppAction::ToggleReplay => self.toggle_replay(),
+            AppAction::OpenConsole => self.console = Some(String::new()),
+            AppAction::ToggleHelp => self.toggle_overlay(OverlayKind::Help),
+            AppAction::ToggleLogHistory => self.toggle_overlay(OverlayKind::Log),
+            AppAction::GammaUp => self.adjust_gamma(GAMMA_STEP),
+            AppAction::GammaDown => self.adjust_gamma(-GAMMA_STEP),
+            AppAction::BrightnessUp => self.adjust_levels(LEVELS_STEP, 0.0),
+            AppAction::BrightnessDown => self.adjust_levels(-LEVELS_STEP, 0.0),
+            AppAction::ContrastUp => self.adjust_levels(0.0, LEVELS_STEP),
+            AppAction::ContrastDown => self.adjust_levels(0.0, -LEVELS_STEP),
+            AppAction::ToggleScanlines => {
+                self.scanlines = !self.scanlines;
+                self.log(
+                    if self.scanlines {
+                        "Scanlines on"
+                    } else {
+                        "Scanlines off"
+                    },
+                    false,
+                );
+            }
+            AppAction::ToggleAspectRatio => {
+                self.aspect_correction = !self.aspect_correction;
+                self.log(
+                    if self.aspect_correction {
+                        "Aspect ratio correction on"
+                    } else {
+                        "Aspect ratio correction off"
+                    },
+                    false,
+                );
+            }
+            AppAction::ToggleReleases => {
+                self.forward_releases = !self.forward_releases;
+                self.log(
+                    if self.forward_releases {
+                        "Forwarding key releases"
+                    } else {
+                        "Ignoring key releases, keys are let go of when they stop repeating"
+                    },
+                    false,
+                );
+            }
+            AppAction::ToggleDebugOverlay => self.debug_overlay = !self.debug_overlay,
+            AppAction::ToggleRenderPause => self.toggle_render_pause(),
+            AppAction::ToggleDemoRecording => self.toggle_demo_recording(),
+            AppAction::ToggleAutoZoom => {
+                self.auto_zoom = !self.auto_zoom;
+                if self.auto_zoom && self.fit_mode == FitMode::Fill {
+                    self.fit_mode = FitMode::Fit;
+                }
+                self.log(
+                    if self.auto_zoom {
+                        "Auto zoom on"
+                    } else {
+                        "Auto zoom off"
+                    },
+                    false,
+                );
+            }
+            AppAction::CycleFitAxis => {
+                // Only auto zoom fits the image, so it's turned on too
+                self.fit_axis = self.fit_axis.next();
+                self.auto_zoom = true;
+                self.fit_mode = FitMode::Fit;
+                self.log(format!("Fitting to {}", self.fit_axis.name()), false);
+            }
+            AppAction::CycleFitMode => self.cycle_fit_mode(),
+            AppAction::CyclePreset => self.cycle_preset(),
+            AppAction::Pause => self.toggle_pause(),
+            // Handled in `handle_key`, since they need to call into Doom
+            AppAction::StepTick
+            | AppAction::StrafeRun
+            | AppAction::SaveState(_)
+            | AppAction::ReloadModule
+            | AppAction::TogglePhotoMode => {}
+        }
+    }
+
+    /// Opens an overlay, or closes it if it's already open.
+    fn toggle_overlay(&mut self, kind: OverlayKind) {
+        self.overlay = match &self.overlay {
+            Some(overlay) if overlay.kind == kind => None,
+            _ => Some(Overlay::new(kind)),
+        };
+    }
+
+    /// Handles a key press while an overlay is open.
+    fn overlay_key(&mut self, code: KeyCode) {
+        let Some(kind) = self.overlay.as_ref().map(|overlay| overlay.kind) else {
+            return;
+        };
+
+        // The overlay keys still work, so the key that opened an overlay
+        // closes it again
+        match self.key_bindings.get(code) {
+            Some(Binding::App(AppAction::ToggleHelp)) => {
+                self.toggle_overlay(OverlayKind::Help);
+                return;
+            }
+            Some(Binding::App(AppAction::ToggleLogHistory)) => {
+                self.toggle_overlay(OverlayKind::Log);
+                return;
+            }
+            _ => {}
+        }
+
+        let lines = self.overlay_lines(kind).len();
+        // Less the overlay's border
+        let page = self
+            .terminal_area()
+            .map_or(1, |area| self.image_area(area).height.saturating_sub(2))
+            .max(1);
+        if let Some(overlay) = &mut self.overlay {
+            if !overlay.handle_key(code, lines, page) {
+                self.overlay = None;
+            }
+        }
+    }
+
+    fn overlay_lines(&self, kind: OverlayKind) -> Vec<Line<'static>> {
+        match kind {
+            OverlayKind::Help => {
+                let mut lines = vec![
+                    Line::from(vec!["<Ctrl+C>".blue().bold(), " Quit".into()]),
+                    Line::from(vec!["<Esc Esc>".blue().bold(), " Quit".into()]),
+                    Line::from(vec![
+                        "<Ctrl+R>".blue().bold(),
+                        " Reload key bindings".into(),
+                    ]),
+                ];
+                let slots = (1..=9).map(|slot| {
+                    (
+                        format!("Save to slot {slot}, Shift to load"),
+                        AppAction::SaveState(slot),
+                    )
+                });
+                let actions = HELP_ACTIONS
+                    .iter()
+                    .map(|(name, action)| (name.to_string(), *action))
+                    .chain(slots);
+                // Unbound actions are left out, like in the instructions
+                for (name, action) in actions {
+                    let keys = self.key_bindings.keys_for(action);
+                    if !keys.is_empty() {
+                        lines.push(Line::from(vec![
+                            format!("<{}>", keys.join("/")).blue().bold(),
+                            format!(" {name}").into(),
+                        ]));
+                    }
+                }
+                lines
+            }
+            OverlayKind::Log => self
+                .log_history
+                .iter()
+                .rev()
+                .map(|(line, error)| {
+                    if *error {
+                        Line::from(line.clone().red())
+                    } else {
+                        Line::from(line.clone())
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// Re-reads the key bindings file. If the file is invalid, the error is
+    /// logged and the current bindings are kept.
+    fn reload_key_bindings(&mut self) {
+        let Some(path) = &self.key_bindings_path else {
+            self.log("No key bindings file to reload, pass one with --keys", true);
+            return;
+        };
+
+        match KeyBindings::load(path) {
+            Ok(key_bindings) => {
+                self.key_bindings = key_bindings;
+                self.log("Reloaded key bindings", false);
+            }
+            Err(e) => self.log(format!("{e:#}"), true),
+        }
+    }
+
+    /// Draws the app to the terminal, using the last frame Doom drew.
+    fn render(&mut self) -> Result<()> {
+        self.flush_log();
+        if self
+            .log_retention
+            .is_some_and(|retention| self.last_log_at.elapsed() >= retention)
+        {
+            self.last_log_line = None;
+        }
+
+        // Checked on every render so we catch the terminal being resized.
+        // The new zoom shows up from the next frame.
+        if self.auto_zoom || self.fit_mode == FitMode::Fill {
+            if let Some(area) = self.terminal_area() {
+                self.fit_zoom(area);
+            }
+        }
+
+        let started = Instant::now();
+        self.last_render = started;
+
+        // The render thread does the drawing, so draw times and the watchdog
+        // don't apply here
+        if let Some(render_thread) = &self.render_thread {
+            return render_thread.send(self.render_to_buffer(render_thread.area()));
+        }
+
+        // For snapshots, only the final screen is saved, once the run is over
+        if self.headless {
+            return Ok(());
+        }
+
+        if let Some(watchdog) = &self.draw_watchdog {
+            watchdog.draw_started();
+        }
+        let result = TERMINAL.with(|t| {
+            t.borrow_mut()
+                .as_mut()
+                .context("Terminal isn't set up")?
+                .draw(|frame| frame.render_widget(&*self, frame.area()))
+                // Ignore the completed frame since we can't return it due to
+                // lifetime issues, and we don't need it anyway
+                .map(|_| ())
+                .context("Failed to draw to the terminal")
+        });
+        if let Some(watchdog) = &self.draw_watchdog {
+            watchdog.draw_finished();
+        }
+        result?;
+
+        if self.adaptive_refresh {
+            // A moving average, so one slow draw doesn't throw us off
+            let took = started.elapsed();
+            self.terminal_draw_time = Some(match self.terminal_draw_time {
+                Some(average) => average.mul_f32(0.9) + took.mul_f32(0.1),
+                None => took,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Deals with an error in a WASM import, where there's no way to return
+    /// it. In safe mode, the error is logged and we carry on as best we can;
+    /// otherwise, we crash.
+    fn recover_or_panic(&mut self, context: &str, error: anyhow::Error) {
+        if self.safe_mode {
+            self.log(format!("{context}: {error:#}"), true);
+        } else {
+            panic!("{context}: {error:?}");
+        }
+    }
+
+    /// How many frames a second the terminal can actually show, as far as we
+    /// can tell from how long drawing takes.
+    fn terminal_refresh_rate(&self) -> Option<u32> {
+        self.terminal_draw_time
+            .filter(|draw_time| !draw_time.is_zero())
+            .map(|draw_time| (1.0 / draw_time.as_secs_f32()).round() as u32)
+    }
+
+    /// Whether the terminal can't keep up with Doom's frames, so drawing
+    /// this one would just hold up the game. Doom draws at most once a
+    /// tick, so when a draw takes longer than a tick, only one frame is
+    /// drawn for every tick's worth of drawing time.
+    fn terminal_is_behind(&self) -> bool {
+        let frame_interval = Duration::from_secs(1) / TICKS_PER_SECOND as u32;
+        self.terminal_draw_time.is_some_and(|draw_time| {
+            let frames_per_draw = draw_time.as_secs_f32() / frame_interval.as_secs_f32();
+            ((self.frames_skipped + 1) as f32) < frames_per_draw
+        })
+    }
+
+    /// Goes through the color filters, then back to no filter.
+    fn cycle_color_filter(&mut self) {
+        let next = match self.color_filter {
+            None => Some(0),
+            Some(filter) => ColorFilter::ALL
+                .iter()
+                .position(|f| *f == filter)
+                .map(|index| index + 1)
+                .filter(|index| *index < ColorFilter::ALL.len()),
+        };
+        self.color_filter = next.map(|index| ColorFilter::ALL[index]);
+        self.log(
+            match self.color_filter {
+                Some(filter) => format!("Color filter: {}", filter.name()),
+                None => "Color filter off".to_string(),
+            },
+            false,
+        );
+    }
+
+    fn map_name(&self) -> Option<String> {
+        match self.current_map? {
+            (Some(episode), map) => Some(format!("E{episode}M{map}")),
+            (None, map) => Some(format!("MAP{map:02}")),
+        }
+    }
+
+    fn toggle_demo_recording(&mut self) {
+        if let Some(demo) = self.demo.take() {
+            let name = format!(
+                "demo-{}.lmp",
+                SystemTime::UNIX_EPOCH
+                    .elapsed()
+                    .map_or(0, |since_epoch| since_epoch.as_secs())
+            );
+            let path = self.demo_dir.join(name);
+            match demo.save(&path) {
+                Ok(()) => self.log(
+                    format!("Saved {} tics of demo to {}", demo.tics(), path.display()),
+                    false,
+                ),
+                Err(e) => self.log(format!("{e:#}"), true),
+            }
+            return;
+        }
+
+        // Doom II has no episodes, and its demos say episode 1
+        let (episode, map) = match self.current_map {
+            Some((episode, map)) => (episode.unwrap_or(1), map),
+            None => (1, 1),
+        };
+        self.demo = Some(DemoRecorder::new(self.demo_skill, episode, map));
+        self.demo_tick = self.game_tick();
+        // Without the map address, there's no telling when a level starts,
+        // so everything from now on is recorded
+        self.log(
+            if self.map_address.is_some() {
+                "Recording demo, it starts with the next level"
+            } else {
+                "Recording demo, start a new level right away for it to play back"
+            },
+            false,
+        );
+    }
+
+    fn toggle_lut(&mut self) {
+        if self.lut.is_none() {
+            self.log("No LUT loaded, pass one with --lut", true);
+            return;
+        }
+        self.lut_enabled = !self.lut_enabled;
+        self.log(
+            if self.lut_enabled {
+                "LUT on"
+            } else {
+                "LUT off"
+            },
+            false,
+        );
+    }
+
+    fn adjust_gamma(&mut self, by: f32) {
+        // Rounded so repeated steps land on round values
+        let value = ((self.gamma.value() + by) * 10.0).round() / 10.0;
+        self.gamma = Gamma::new(value);
+        self.log(format!("Gamma: {:.1}", self.gamma.value()), false);
+    }
+
+    fn adjust_levels(&mut self, brightness_by: f32, contrast_by: f32) {
+        // Rounded so repeated steps land on round values
+        let round = |value: f32| (value * 10.0).round() / 10.0;
+        self.levels = Levels::new(
+            round(self.levels.brightness() + brightness_by),
+            round(self.levels.contrast() + contrast_by),
+        );
+        self.log(self.describe_levels(), false);
+    }
+
+    fn describe_levels(&self) -> String {
+        format!(
+            "Brightness: {:+.1}, contrast: {:.1}",
+            self.levels.brightness(),
+            self.levels.contrast()
+        )
+    }
+
+    fn toggle_turbo(&mut self) {
+        self.turbo = !self.turbo;
+        self.update_clock_speed();
+    }
+
+    /// Plays back the frames in the replay buffer, pausing the game
+    /// meanwhile, or stops a replay that's going.
+    fn toggle_replay(&mut self) {
+        if self.replay_position.is_some() {
+            self.stop_replay();
+            return;
+        }
+        let Some(replay_buffer) = &self.replay_buffer else {
+            self.log("No replay buffer, pass a size with --replay-mem", true);
+            return;
+        };
+        if replay_buffer.is_empty() {
+            self.log("Nothing to replay yet", true);
+            return;
+        }
+
+        let seconds = replay_buffer.len() as f32 / TICKS_PER_SECOND as f32;
+        self.replay_was_paused = self.paused;
+        self.paused = true;
+        self.update_clock_speed();
+        self.replay_position = Some(0);
+        self.replay_frame_at = None;
+        self.log(format!("Replaying the last {seconds:.1} seconds"), false);
+    }
+
+    fn stop_replay(&mut self) {
+        self.replay_position = None;
+        self.paused = self.replay_was_paused;
+        self.update_clock_speed();
+        self.log("Replay over", false);
+    }
+
+    /// Shows the next replay frame once it's due, at Doom's frame rate.
+    fn advance_replay(&mut self) -> Result<()> {
+        let Some(position) = self.replay_position else {
+            return Ok(());
+        };
+        let frame_interval = Duration::from_secs(1) / TICKS_PER_SECOND as u32;
+        if self
+            .replay_frame_at
+            .is_some_and(|at| at.elapsed() < frame_interval)
+        {
+            return Ok(());
+        }
+
+        let Some(frame) = self
+            .replay_buffer
+            .as_ref()
+            .and_then(|replay_buffer| replay_buffer.frame(position))
+        else {
+            self.stop_replay();
+            return Ok(());
+        };
+        self.replay_position = Some(position + 1);
+        self.replay_frame_at = Some(Instant::now());
+        self.render_cache.clear();
+
+        if self.ascii {
+            self.ascii_frame = Some(frame);
+        } else if !self.stats_only {
+            self.current_frame = Some(
+                self.image_backend
+                    .encode(
+                        image::DynamicImage::ImageRgba8(frame),
+                        Rect::new(0, 0, 640, 400),
+                        false,
+                    )
+                    .context("Failed to encode replay frame")?,
+            );
+        }
+        self.render()
+    }
+
+    fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.update_clock_speed();
+        // Doom won't draw while paused, so show that we're paused ourselves
+        if let Err(e) = self.render() {
+            self.recover_or_panic("Failed to draw screen", e);
+        }
+    }
+
+    fn toggle_render_pause(&mut self) {
+        self.render_paused = !self.render_paused;
+        self.log(
+            if self.render_paused {
+                "Rendering paused"
+            } else {
+                "Rendering resumed"
+            },
+            false,
+        );
+        // Shows the message now; resuming shows the game from the next frame
+        if let Err(e) = self.render() {
+            self.recover_or_panic("Failed to draw screen", e);
+        }
+    }
+
+    fn update_clock_speed(&mut self) {
+        self.clock.set_speed(match (self.paused, self.turbo) {
+            (true, _) => 0,
+            (false, true) => TURBO_SPEED,
+            (false, false) => 1,
+        });
+    }
+
+    /// The game time at which the next tick starts.
+    fn next_tick_time(&self) -> Duration {
+        let next_tick_millis = ((self.game_tick() + 1) * 1000).div_ceil(TICKS_PER_SECOND);
+        Duration::from_millis(next_tick_millis as u64)
+    }
+
+    /// Moves the clock to the start of the next tick.
+    fn advance_clock_one_tick(&mut self) {
+        let next_tick = self.next_tick_time();
+        self.clock
+            .advance(next_tick.saturating_sub(self.clock.elapsed()));
+    }
+
+    fn cycle_protocol_type(&mut self) {
+        if self.ascii {
+            self.ascii = false;
+        } else if self.image_backend.on_last_protocol() {
+            // We went through all the image protocols, so ASCII is next
+            self.ascii = true;
+            self.apply_protocol_zoom();
+            return;
+        }
+        self.image_backend.next_protocol();
+        // The new protocol may zoom differently
+        self.rebuild_picker();
+        self.apply_protocol_zoom();
+    }
+
+    fn cycle_preset(&mut self) {
+        if self.presets.is_empty() {
+            self.log("No view presets, add some with --view-preset", true);
+            return;
+        }
+        let index = self
+            .preset_index
+            .map_or(0, |index| (index + 1) % self.presets.len());
+        self.preset_index = Some(index);
+
+        let preset = self.presets[index].clone();
+        if let Some(protocol) = &preset.protocol {
+            self.ascii = protocol == "ascii";
+            if !self.ascii {
+                self.image_backend.select_protocol(protocol);
+                self.rebuild_picker();
+            }
+        }
+        if let Some(fit_axis) = preset.fit_axis {
+            self.fit_axis = fit_axis;
+        }
+        if let Some(color_filter) = preset.color_filter {
+            self.color_filter = color_filter;
+        }
+        match preset.zoom {
+            Some(PresetZoom::Auto) => {
+                self.auto_zoom = true;
+                self.fit_mode = FitMode::Fit;
+            }
+            Some(PresetZoom::Fixed(zoom)) => {
+                self.auto_zoom = false;
+                self.fit_mode = FitMode::Fit;
+                self.set_zoom(zoom);
+            }
+            // Like switching protocols any other way
+            None if preset.protocol.is_some() => self.apply_protocol_zoom(),
+            None => {}
+        }
+        self.log(format!("View preset: {}", preset.name), false);
+    }
+
+    fn cycle_fit_mode(&mut self) {
+        self.fit_mode = self.fit_mode.next();
+        match self.fit_mode {
+            // Fitting the image is what auto zoom is for
+            FitMode::Fit => self.auto_zoom = true,
+            // Zoomed to cover the space on the next render
+            FitMode::Fill => self.auto_zoom = false,
+            FitMode::Actual | FitMode::Center => {
+                self.auto_zoom = false;
+                self.set_zoom(1);
+            }
+        }
+        self.log(format!("Fit mode: {}", self.fit_mode.name()), false);
+    }
+
+    fn zoom_strategy(&self) -> ZoomStrategy {
+        self.zoom_strategies
+            .get(&self.protocol_name().to_lowercase())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// How much the image itself is scaled up for the zoom, rather than
+    /// the font size scaled down.
+    fn image_scale(&self) -> u32 {
+        match self.zoom_strategy() {
+            ZoomStrategy::Font => 1,
+            ZoomStrategy::Scale => self.zoom as u32,
+        }
+    }
+
+    /// Switches to the zoom configured for the current protocol, if any.
+    fn apply_protocol_zoom(&mut self) {
+        if let Some(&zoom) = self.protocol_zoom.get(&self.protocol_name().to_lowercase()) {
+            self.set_zoom(zoom);
+        }
+    }
+
+    fn protocol_name(&self) -> String {
+        if self.ascii {
+            "ASCII".to_string()
+        } else {
+            self.image_backend.protocol_name()
+        }
+    }
+
+    fn set_zoom(&mut self, zoom: u16) {
+        let max_zoom = self.max_zoom();
+        let zoom = if self.clamp_zoom && zoom > max_zoom {
+            self.log(format!("Max zoom is {max_zoom}x"), false);
+            max_zoom
+        } else {
+            zoom
+        };
+        self.zoom = zoom;
+        self.rebuild_picker();
+    }
+
+    fn rebuild_picker(&mut self) {
+        // At lower quality, the image is smaller, so we pretend the font is
+        // smaller too to keep it the same size on screen
+        let divisor = match self.zoom_strategy() {
+            ZoomStrategy::Font => self.zoom * self.quality_divisor,
+            ZoomStrategy::Scale => self.quality_divisor,
+        };
+        // Never below one pixel per cell, however far we zoom in
+        self.image_backend.set_font_size((
+            (self.default_font_size.0 / divisor).max(1),
+            (self.default_font_size.1 / divisor).max(1),
+        ));
+        self.render_cache.clear();
+        // No need to recreate the image, display will be updated next frame anyway
+    }
+
+    /// Lowers the image resolution if rendering takes longer than the frame
+    /// budget, and raises it again when there's plenty of headroom.
+    fn adapt_quality(&mut self, render_time: Duration) {
+        let Some(budget) = self.frame_budget else {
+            return;
+        };
+
+        let average = match self.render_time {
+            Some(average) => average.mul_f32(0.9) + render_time.mul_f32(0.1),
+            None => render_time,
+        };
+        self.render_time = Some(average);
+
+        let divisor = if average > budget {
+            self.quality_divisor + 1
+        } else if average < budget / 2 {
+            self.quality_divisor - 1
+        } else {
+            return;
+        }
+        .clamp(1, MAX_QUALITY_DIVISOR);
+
+        if divisor != self.quality_divisor {
+            self.quality_divisor = divisor;
+            // Start measuring again at the new quality
+            self.render_time = None;
+            self.rebuild_picker();
+        }
+    }
+
+    fn increment_zoom(&mut self) {
+        // Zooming by hand means the user wants control back
+        self.auto_zoom = false;
+        if self.fit_mode == FitMode::Fill {
+            self.fit_mode = FitMode::Fit;
+        }
+        self.set_zoom(self.zoom.saturating_add(1));
+    }
+
+    fn decrement_zoom(&mut self) {
+        self.auto_zoom = false;
+        if self.fit_mode == FitMode::Fill {
+            self.fit_mode = FitMode::Fit;
+        }
+        self.set_zoom(self.zoom.saturating_sub(1).max(1));
+    }
+
+    /// Renders the app into a buffer in memory rather than to the terminal,
+    /// e.g. to capture what's on screen.
+    pub fn render_to_buffer(&self, area: Rect) -> Buffer {
+        let mut buffer = Buffer::empty(area);
+        Widget::render(self, area, &mut buffer);
+        buffer
+    }
+
+    /// Renders the app as plain text, one line per row. Images drawn with a
+    /// graphics protocol don't show up in text, but ASCII mode does.
+    pub fn render_to_string(&self, area: Rect) -> String {
+        let buffer = self.render_to_buffer(area);
+        let mut text = String::new();
+        for y in area.top()..area.bottom() {
+            let line: String = (area.left()..area.right())
+                .map(|x| buffer[(x, y)].symbol())
+                .collect();
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        text
+    }
+
+    fn terminal_area(&self) -> Option<Rect> {
+        if let Some(snapshot) = &self.snapshot {
+            return Some(snapshot.area);
+        }
+        if let Some(render_thread) = &self.render_thread {
+            return Some(render_thread.area());
+        }
+        TERMINAL.with(|t| {
+            let size = t.borrow().as_ref()?.size().ok()?;
+            Some(Rect::new(0, 0, size.width, size.height))
+        })
+    }
+
+    /// Saves the last frame to a PNG in the current directory, named after
+    /// the time so photos don't overwrite each other.
+    fn take_photo(&mut self) {
+        let Some(frame) = &self.photo_frame else {
+            self.log("Nothing to take a photo of yet", true);
+            return;
+        };
+        let millis = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = PathBuf::from(format!("doom-photo-{millis}.png"));
+        match frame.save(&path) {
+            Ok(()) => self.log(format!("Saved photo to {}", path.display()), false),
+            Err(e) => self.log(format!("Failed to save photo: {e}"), true),
+        }
+    }
+
+    /// Writes the status file, if there is one and it's time to.
+    fn write_status(&mut self) {
+        if !self.status_file.as_ref().is_some_and(StatusFile::is_due) {
+            return;
+        }
+        let status = Status {
+            fps: self.fps(),
+            tick: self.game_tick(),
+            elapsed: self.started_at.elapsed(),
+            protocol: self.protocol_name(),
+            zoom: self.zoom,
+        };
+        if let Some(Err(e)) = self
+            .status_file
+            .as_mut()
+            .map(|status_file| status_file.write(&status))
+        {
+            self.log(format!("{e:#}"), true);
+        }
+    }
+
+    /// Drops ticks Doom is too far behind on, so an overloaded machine
+    /// doesn't spiral: each step running more ticks, taking longer, and
+    /// leaving even more for the next one.
+    fn limit_lag(&mut self) {
+        if let Some(max_lag) = self.max_lag {
+            let dropped = self.clock.limit_lag(max_lag);
+            if !dropped.is_zero() {
+                if self.dropped_ticks == 0 {
+                    self.log("Falling behind, dropping ticks to keep up", true);
+                }
+                self.dropped_ticks += (dropped.as_millis() * TICKS_PER_SECOND / 1000) as u64;
+            }
+        }
+        // Doom catches up to whatever the clock says when it's stepped
+        self.clock.mark();
+    }
+
+    /// How tall the image is once it's been corrected for aspect ratio, if
+    /// it is. Doom's 320x200 was shown on 4:3 screens, with pixels 20%
+    /// taller than wide, which square terminal pixels squash flat.
+    fn image_height(&self) -> u32 {
+        if self.aspect_correction {
+            self.frame_size.1 * 6 / 5
+        } else {
+            self.frame_size.1
+        }
+    }
+
+    /// The framebuffer size that fills the image area in a terminal of this
+    /// size, going by how many pixels the current protocol draws per cell.
+    fn matching_resolution(&self, area: Rect) -> (u32, u32) {
+        let image_area = self.image_area(area);
+        // Half blocks and ASCII art draw two pixels per cell, one above the
+        // other; the rest draw a whole font's worth
+        let (cell_width, cell_height) = match self.protocol_name().as_str() {
+            "ASCII" | "Halfblocks" => (1, 2),
+            _ => (
+                self.default_font_size.0 as u32,
+                self.default_font_size.1 as u32,
+            ),
+        };
+        (
+            (image_area.width as u32 * cell_width).clamp(1, MAX_FRAME_SIZE),
+            (image_area.height as u32 * cell_height).clamp(1, MAX_FRAME_SIZE),
+        )
+    }
+
+    /// Sets the biggest zoom at which the whole image still fits in the
+    /// terminal.
+    fn fit_zoom(&mut self, area: Rect) {
+        let mut zoom = self.fitting_zoom(area);
+        // Clamped here too, or `set_zoom` would complain on every render
+        if self.clamp_zoom {
+            zoom = zoom.min(self.max_zoom());
+        }
+        if zoom != self.zoom {
+            self.set_zoom(zoom);
+        }
+    }
+
+    /// The biggest zoom worth having. Past it, the image doesn't fit the
+    /// terminal any more, or with font zoom, the font can't get any smaller
+    /// and every step renders the same.
+    fn max_zoom(&self) -> u16 {
+        let Some(area) = self.terminal_area() else {
+            return u16::MAX;
+        };
+        let max_zoom = self.fitting_zoom(area);
+        match self.zoom_strategy() {
+            ZoomStrategy::Font => {
+                let (font_width, font_height) = self.default_font_size;
+                max_zoom.min((font_width.min(font_height) / self.quality_divisor).max(1))
+            }
+            ZoomStrategy::Scale => max_zoom,
+        }
+    }
+
+    /// The biggest zoom at which the image fits the terminal, along the
+    /// axes we're fitting to.
+    fn fitting_zoom(&self, area: Rect) -> u16 {
+        let available = self.image_area(area);
+        let (font_width, font_height) = self.default_font_size;
+        // At zoom z, the image takes up width * z / font_width cells across
+        let width_zoom = available.width as u32 * font_width as u32 / self.frame_size.0;
+        let height_zoom = available.height as u32 * font_height as u32 / self.image_height();
+        match (self.fit_mode, self.fit_axis) {
+            // Rounded up instead, so no space is left around the image
+            (FitMode::Fill, _) => (available.width as u32 * font_width as u32)
+                .div_ceil(self.frame_size.0)
+                .max((available.height as u32 * font_height as u32).div_ceil(self.image_height())),
+            (_, FitAxis::Both) => width_zoom.min(height_zoom),
+            (_, FitAxis::Width) => width_zoom,
+            (_, FitAxis::Height) => height_zoom,
+        }
+        .clamp(1, u16::MAX as u32) as u16
+    }
+}
+
+fn log_string(mut env: FunctionEnvMut<DoomApp>, offset: i32, length: i32, error: bool) {
+    let line = read_bytes(&env, offset, length);
+    let app = env.data_mut();
+    match line {
+        Ok(line) => app.queue_log(line, error),
+        Err(e) => app.recover_or_panic("Failed to read log line", e),
+    }
+}
+
+fn read_bytes(env: &FunctionEnvMut<DoomApp>, offset: i32, length: i32) -> Result<Vec<u8>> {
+    let view = env.data().memory.view(env);
+    Ok(WasmSlice::new(&view, offset as u64, length as u64)?.read_to_vec()?)
+}
+
+fn log_string_normal(env: FunctionEnvMut<DoomApp>, offset: i32, length: i32) {
+    log_string(env, offset, length, false);
+}
+
+fn log_string_error(env: FunctionEnvMut<DoomApp>, offset: i32, length: i32) {
+    log_string(env, offset, length, true);
+}
+
+fn milliseconds_since_start(env: FunctionEnvMut<DoomApp>) -> i32 {
+    env.data().clock.elapsed().as_millis() as i32
+}
+
+/// Lets Doom builds that want a seed get one, so runs can be made
+/// reproducible with `--seed`. The bundled module doesn't import this: its
+/// RNG is vanilla Doom's fixed table, which is reset at the start of every
+/// level, so it's deterministic without a seed given the same input timing.
+fn random_seed(env: FunctionEnvMut<DoomApp>) -> i32 {
+    env.data().seed as i32
+}
+
+/// Lets builds with indexed framebuffers say which palette the next frames
+/// use, for Doom's damage, pickup and radiation suit tints. Builds that
+/// draw in RGB apply the palette themselves and don't need this.
+fn set_palette(mut env: FunctionEnvMut<DoomApp>, index: i32) {
+    env.data_mut().palette_index = usize::try_from(index).unwrap_or(0);
+}
+
+fn pwad_count(env: FunctionEnvMut<DoomApp>) -> i32 {
+    env.data().pwads.len() as i32
+}
+
+/// The size of a PWAD in bytes, or -1 if there's no PWAD at `index`.
+fn pwad_size(env: FunctionEnvMut<DoomApp>, index: i32) -> i32 {
+    usize::try_from(index)
+        .ok()
+        .and_then(|index| env.data().pwads.get(index))
+        .map_or(-1, |pwad| pwad.data.len() as i32)
+}
+
+/// Copies a PWAD from `--pwad` into WASM memory at `offset`, which needs
+/// room for `js_pwad_size(index)` bytes, and returns 0, or -1 on failure.
+///
+/// The bundled module has its IWAD built in and no way to read files, so it
+/// can't load PWADs. Builds that can should go through `js_pwad_count` and
+/// load each PWAD in order, the way `-file a.wad b.wad` would.
+fn pwad_read(mut env: FunctionEnvMut<DoomApp>, index: i32, offset: i32) -> i32 {
+    let (app, store) = env.data_and_store_mut();
+    let Some(pwad) = usize::try_from(index)
+        .ok()
+        .and_then(|index| app.pwads.get(index))
+    else {
+        return -1;
+    };
+
+    let result = app
+        .memory
+        .view(&store)
+        .write(offset as u32 as u64, &pwad.data)
+        .with_context(|| format!("Failed to load PWAD {}", pwad.name()));
+    match result {
+        Ok(()) => {
+            let message = format!("Loaded PWAD {}", pwad.name());
+            app.log(message, false);
+            0
+        }
+        Err(e) => {
+            app.log(format!("{e:#}"), true);
+            -1
+        }
+    }
+}
+
+fn draw_screen(mut env: FunctionEnvMut<DoomApp>, offset: i32) {
+    if let Err(e) = try_draw_screen(&mut env, offset, true) {
+        env.data_mut().recover_or_panic("Failed to draw screen", e);
+    }
+}
+
+/// Like `draw_screen`, for Doom builds that also pass the rectangle that
+/// changed since the last frame. Builds that don't know about it keep
+/// importing `js_draw_screen`, so nothing changes for them.
+///
+/// The framebuffer is still the whole screen, and the rectangle only tells
+/// us whether anything changed. ratatui-image can't patch part of an
+/// encoded image, so any change at all means encoding the whole frame
+/// again; all we can skip is re-encoding when nothing visible changed.
+fn draw_screen_region(
+    mut env: FunctionEnvMut<DoomApp>,
+    offset: i32,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+) {
+    let (frame_width, frame_height) = env.data().frame_size;
+    let changed = width > 0
+        && height > 0
+        && x < frame_width as i32
+        && y < frame_height as i32
+        && x.saturating_add(width) > 0
+        && y.saturating_add(height) > 0;
+    if let Err(e) = try_draw_screen(&mut env, offset, changed) {
+        env.data_mut().recover_or_panic("Failed to draw screen", e);
+    }
+}
+
+/// The framebuffer's size, unless the build says otherwise.
+const DEFAULT_FRAME_SIZE: (u32, u32) = (640, 400);
+
+/// The biggest framebuffer a build may report, each way. Anything bigger is
+/// more likely garbage than a real resolution.
+const MAX_FRAME_SIZE: u32 = 8192;
+
+/// Reads the framebuffer Doom just drew. With the `zero-copy` feature, this
+/// borrows the WASM memory instead of copying out of it, so frames are only
+/// copied when something actually needs its own copy.
+#[cfg(feature = "zero-copy")]
+fn read_framebuffer<'a>(
+    view: &'a MemoryView,
+    offset: i32,
+    (width, height): (u32, u32),
+    format: PixelFormat,
+) -> Result<Cow<'a, [u8]>> {
+    let size = width as u64 * height as u64 * format.bytes_per_pixel();
+    // SAFETY: Doom isn't running while we're inside one of its imports, so
+    // nothing can write to or grow the memory while the slice is alive
+    let memory = unsafe { view.data_unchecked() };
+    memory
+        .get(offset as u32 as usize..)
+        .and_then(|rest| rest.get(..size as usize))
+        .map(Cow::Borrowed)
+        .context("Failed to read framebuffer")
+}
+
+#[cfg(not(feature = "zero-copy"))]
+fn read_framebuffer<'a>(
+    view: &'a MemoryView,
+    offset: i32,
+    (width, height): (u32, u32),
+    format: PixelFormat,
+) -> Result<Cow<'a, [u8]>> {
+    let size = width as u64 * height as u64 * format.bytes_per_pixel();
+    WasmSlice::new(view, offset as u64, size)
+        .and_then(|slice| slice.read_to_vec())
+        .map(Cow::Owned)
+        .context("Failed to read framebuffer")
+}
+
+fn try_draw_screen(env: &mut FunctionEnvMut<DoomApp>, offset: i32, changed: bool) -> Result<()> {
+    let (app, mut store) = env.data_and_store_mut();
+
+    // Asked every frame, since the resolution can change at any time
+    if let Some((width, height)) = app.screen_size.clone() {
+        let width = width
+            .call(&mut store)
+            .context("Failed to call doom_screen_width")?;
+        let height = height
+            .call(&mut store)
+            .context("Failed to call doom_screen_height")?;
+        app.set_frame_size(width, height)?;
+    }
+
+    let view = app.memory.view(&store);
+    let palette = app
+        .palettes
+        .as_ref()
+        .map(|palettes| palettes.get(app.palette_index));
+    let image_data = app.pixel_format.to_rgba(
+        read_framebuffer(&view, offset, app.frame_size, app.pixel_format)?,
+        palette,
+    );
+
+    let read_i32 = |address: u32| {
+        let mut bytes = [0u8; 4];
+        view.read(address as u64, &mut bytes)
+            .ok()
+            .map(|_| i32::from_le_bytes(bytes))
+    };
+    let health = app.death_alert.and_then(read_i32);
+    let map = app.map_address.and_then(read_i32);
+    let episode = app.episode_address.and_then(read_i32);
+
+    let menu_flag = app
+        .menu_detector
+        .as_ref()
+        .and_then(MenuDetector::address)
+        .and_then(read_i32);
+
+    // Anything out of range means the addresses are wrong, or the game
+    // hasn't started yet; either way, there's nothing sensible to show
+    let current_map = match (episode, map) {
+        (Some(episode @ 1..=9), Some(map @ 1..=9)) => Some((Some(episode as u8), map as u8)),
+        (None, Some(map @ 1..=99)) if app.episode_address.is_none() => Some((None, map as u8)),
+        _ => None,
+    };
+    if current_map != app.current_map {
+        if let (Some(demo), Some((episode, map))) = (&mut app.demo, current_map) {
+            // Doom II has no episodes, and its demos say episode 1
+            demo.start_level(episode.unwrap_or(1), map);
+            app.demo_tick = app.game_tick();
+        }
+    }
+    app.current_map = current_map;
+
+    if let Some(health) = health {
+        if health <= 0 && app.player_health.is_some_and(|previous| previous > 0) {
+            app.on_player_death();
+        }
+        app.player_health = Some(health);
+    }
+
+    app.present_frame(image_data, menu_flag, changed)
+}
+
+impl DoomApp {
+    /// Switches to a new framebuffer size, if it changed. Everything after
+    /// reading the framebuffer goes by `frame_size`, so the next frame just
+    /// comes out at the new size.
+    fn set_frame_size(&mut self, width: i32, height: i32) -> Result<()> {
+        let size = match (u32::try_from(width), u32::try_from(height)) {
+            (Ok(width @ 1..=MAX_FRAME_SIZE), Ok(height @ 1..=MAX_FRAME_SIZE)) => (width, height),
+            _ => bail!("Doom reported an invalid screen size of {width}x{height}"),
+        };
+        if size != self.frame_size {
+            self.frame_size = size;
+            self.render_cache.clear();
+            self.log(format!("Resolution changed to {width}x{height}"), false);
+        }
+        Ok(())
+    }
+
+    /// Takes in a frame Doom drew, as RGBA at `frame_size`, and does
+    /// everything we do with frames: post-processing, recording, encoding,
+    /// counting and drawing. `menu_flag` is the value at the menu detection address, if
+    /// there is one.
+    ///
+    /// Nothing here touches WASM memory, so frames can also be fed in
+    /// directly, e.g. to test the render path with known patterns.
+    pub fn present_frame(
+        &mut self,
+        mut image_data: Cow<[u8]>,
+        menu_flag: Option<i32>,
+        changed: bool,
+    ) -> Result<()> {
+        if let Some(menu_detector) = &mut self.menu_detector {
+            self.in_menu = menu_detector.update(&image_data, menu_flag);
+        }
+
+        if let Some(frame_hashes) = &mut self.frame_hashes {
+            // Hashed before any post-processing, so only the game itself matters
+            match frame_hashes.add(frame_hash::hash(&image_data)) {
+                Ok(Some(divergence)) => self.log(divergence.to_string(), true),
+                Ok(None) => {}
+                Err(e) => self.log(format!("{e:#}"), true),
+            }
+        }
+
+        let (width, height) = self.frame_size;
+
+        if let Some(lut) = self.lut.as_ref().filter(|_| self.lut_enabled) {
+            let mut frame = image::RgbaImage::from_raw(width, height, image_data.into_owned())
+                .context("Framebuffer has the wrong size")?;
+            lut.apply(&mut frame);
+            image_data = Cow::Owned(frame.into_raw());
+        }
+
+        // After grading, so the compensation works on the colors actually shown
+        if let Some(color_filter) = self.color_filter {
+            let mut frame = image::RgbaImage::from_raw(width, height, image_data.into_owned())
+                .context("Framebuffer has the wrong size")?;
+            color_filter.apply(&mut frame);
+            image_data = Cow::Owned(frame.into_raw());
+        }
+
+        if !self.gamma.is_identity() {
+            let mut data = image_data.into_owned();
+            self.gamma.apply(&mut data);
+            image_data = Cow::Owned(data);
+        }
+
+        if !self.levels.is_identity() {
+            let mut data = image_data.into_owned();
+            self.levels.apply(&mut data);
+            image_data = Cow::Owned(data);
+        }
+
+        if self.scanlines {
+            let mut data = image_data.into_owned();
+            apply_scanlines(&mut data, width);
+            image_data = Cow::Owned(data);
+        }
+
+        let frame = image::ImageBuffer::<image::Rgba<u8>, _>::from_raw(width, height, &*image_data)
+            .context("Framebuffer has the wrong size")?;
+
+        let (game_time, tick) = (self.clock.elapsed(), self.game_tick());
+        if let Some(frame_dump) = &mut self.frame_dump {
+            let was_full = frame_dump.is_full();
+            let result = frame_dump.write(&frame, game_time, tick).map(|_| {
+                (!was_full && frame_dump.is_full()).then(|| {
+                    format!(
+                        "Frame dump limit reached, wrote {} frames to {}",
+                        frame_dump.written(),
+                        frame_dump.dir().display()
+                    )
+                })
+            });
+            match result {
+                Ok(Some(message)) => self.log(message, false),
+                Ok(None) => {}
+                Err(e) => self.log(format!("{e:#}"), true),
+            }
+        }
+
+        if let Some(frame_stream) = &mut self.frame_stream {
+            frame_stream.send(&image_data, width, height);
+        }
+
+        if let Some(replay_buffer) = &mut self.replay_buffer {
+            replay_buffer.push(&frame);
+        }
+
+        if self.photo_mode {
+            self.photo_frame = image::RgbaImage::from_raw(width, height, image_data.to_vec());
+        }
+
+        // Everything above is for recording, so it still sees every frame
+        if self.render_paused {
+            self.missed_changes |= changed;
+            return Ok(());
+        }
+
+        if self.adaptive_refresh {
+            if self.terminal_is_behind() {
+                self.frames_skipped += 1;
+                self.missed_changes |= changed;
+                return Ok(());
+            }
+            self.frames_skipped = 0;
+        }
+
+        // Menus barely change, so there's no need to show every frame
+        if self.in_menu && self.last_render.elapsed() < MENU_RENDER_INTERVAL {
+            return Ok(());
+        }
+
+        // Everything above still sees every frame; only the expensive part is
+        // skipped. Doom draws again soon enough to catch up.
+        if self
+            .min_draw_interval
+            .is_some_and(|interval| self.last_render.elapsed() < interval)
+        {
+            return Ok(());
+        }
+
+        let render_started = Instant::now();
+        let changed = changed || std::mem::take(&mut self.missed_changes);
+
+        let reused = changed && !self.ascii && !self.stats_only && {
+            let key = (
+                render_cache::hash(&image_data),
+                self.quality_divisor,
+                self.aspect_correction,
+                self.fit_axis,
+                self.fit_mode,
+                self.terminal_area(),
+            );
+            self.render_cache.check(key)
+        };
+
+        if !changed || reused {
+            // The last frame's protocol still shows exactly this
+        } else if self.ascii {
+            self.ascii_frame = Some(if self.aspect_correction {
+                image::imageops::resize(&frame, width, self.image_height(), FilterType::Triangle)
+            } else {
+                image::RgbaImage::from_raw(width, height, image_data.into_owned())
+                    .context("Framebuffer has the wrong size")?
+            });
+        } else if !self.stats_only {
+            // Building the protocol is the expensive part of rendering, and
+            // there's no point to it if we aren't showing the image anyway
+            let scale = self.image_scale();
+            let dynamic_image = if self.quality_divisor > 1 || self.aspect_correction || scale > 1 {
+                // Resized straight from the framebuffer, without a full size copy
+                image::DynamicImage::ImageRgba8(image::imageops::resize(
+                    &frame,
+                    width * scale / self.quality_divisor as u32,
+                    self.image_height() * scale / self.quality_divisor as u32,
+                    FilterType::Triangle,
+                ))
+            } else {
+                image::DynamicImage::ImageRgba8(
+                    image::RgbaImage::from_raw(width, height, image_data.into_owned())
+                        .context("Framebuffer has the wrong size")?,
+                )
+            };
+            // When fitting one axis, filling the space or showing the image
+            // at 1x, the image can be too big for the space, and protocols
+            // won't draw an image that doesn't fit, so it's cropped to the
+            // space we have
+            let crop = self.fit_mode != FitMode::Fit || self.fit_axis != FitAxis::Both;
+            let (target, crop) = match self.terminal_area() {
+                Some(area) if crop && !self.terminal_too_small(area) => {
+                    (self.image_area(area), true)
+                }
+                _ => (Rect::new(0, 0, 640, 400), false),
+            };
+            self.current_frame = Some(self.image_backend.encode(dynamic_image, target, crop)?);
+        }
+
+        self.frame_stats.record_frame()?;
+
+        self.render()?;
+        if changed && !reused {
+            self.adapt_quality(render_started.elapsed());
+        }
+        Ok(())
+    }
+}
+
+/// Darkens every other row of a frame, for a CRT look. Doom draws at 320x200
+/// and the framebuffer doubles that, so this darkens the bottom half of
+/// each of Doom's rows.
+fn apply_scanlines(frame: &mut [u8], width: u32) {
+    for row in frame
+        .chunks_exact_mut(width as usize * 4)
+        .skip(1)
+        .step_by(2)
+    {
+        for pixel in row.chunks_exact_mut(4) {
+            for channel in &mut pixel[..3] {
+                *channel = (*channel as u16 * SCANLINE_BRIGHTNESS / 256) as u8;
+            }
+        }
+    }
+}
+
+impl DoomApp {
+    // I'm not that good with ratatui, let's just do some manual math and
+    // draw over the empty part of the block
+    fn image_area(&self, area: Rect) -> Rect {
+        self.margins.apply(area)
+    }
+
+    /// Whether there's too little room to show the image at all, including
+    /// when the margins take up all of it.
+    fn terminal_too_small(&self, area: Rect) -> bool {
+        let image_area = self.image_area(area);
+        area.width < MIN_TERMINAL_WIDTH
+            || area.height < MIN_TERMINAL_HEIGHT
+            || image_area.width == 0
+            || image_area.height == 0
+    }
+}
+
+impl Widget for &DoomApp {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mut title = vec![" WASM DooM in TUI".bold()];
+        // The FPS depends on how fast the machine is, and a snapshot has to
+        // come out the same everywhere
+        if self.snapshot.is_none() {
+            title.push(" - FPS: ".bold());
+            title.push(if self.fps_is_low() {
+                format!("{} ⚠", self.fps()).red().bold()
+            } else {
+                self.fps().to_string().bold()
+            });
+        }
+        title.push(" - Protocol: ".bold());
+        title.push(self.protocol_name().bold());
+        if let Some(index) = self.preset_index {
+            title.push(format!(" - Preset: {}", self.presets[index].name).bold());
+        }
+        if let Some(color_filter) = self.color_filter {
+            title.push(format!(" - Filter: {}", color_filter.name()).bold());
+        }
+        if self.scanlines {
+            title.push(" - Scanlines".bold());
+        }
+        if self.aspect_correction {
+            title.push(" - AR: on".bold());
+        }
+        if !self.gamma.is_identity() {
+            title.push(format!(" - Gamma: {:.1}", self.gamma.value()).bold());
+        }
+        if !self.levels.is_identity() {
+            title.push(format!(" - {}", self.describe_levels()).bold());
+        }
+        if let Some(map_name) = self.map_name() {
+            title.push(format!(" - {map_name}").bold());
+        }
+        if self.frame_budget.is_some() {
+            title.push(format!(" - Quality: 1/{}", self.quality_divisor).bold());
+        }
+        if let Some(hash) = self.frame_hashes.as_ref().and_then(FrameHashes::last) {
+            if self.show_hash {
+                title.push(format!(" - Hash: {hash:016x}").bold());
+            }
+        }
+        if let Some(hz) = self.terminal_refresh_rate() {
+            title.push(format!(" - Terminal: ~{hz} Hz").bold());
+        }
+        if self.replay_position.is_some() {
+            title.push(" ⏪".bold());
+        } else if self.paused {
+            title.push(" ⏸".bold());
+        }
+        title.push(if self.turbo { " ⏩ " } else { " " }.bold());
+        let title = Line::from(title);
+        // Built from the bindings so remapped keys show up correctly.
+        // Unbound actions are left out.
+        let mut instructions = vec![];
+        for (name, action) in [
+            ("Quit".to_string(), AppAction::Quit),
+            (
+                "Switch Image Protocol".to_string(),
+                AppAction::CycleProtocol,
+            ),
+            ("Increase Zoom".to_string(), AppAction::ZoomIn),
+            ("Decrease Zoom".to_string(), AppAction::ZoomOut),
+            (
+                format!("Fit Mode: {}", self.fit_mode.name()),
+                AppAction::CycleFitMode,
+            ),
+        ] {
+            let keys = self.key_bindings.keys_for(action);
+            if keys.is_empty() {
+                continue;
+            }
+            let separator = if instructions.is_empty() { " " } else { " - " };
+            instructions.push(format!("{separator}{name} ").into());
+            instructions.push(format!("<{}>", keys.join("/")).blue().bold());
+        }
+        instructions.push(" ".into());
+        let instructions = Line::from(instructions);
+        let border_style = if self
+            .death_flash_started
+            .is_some_and(|at| at.elapsed() < DEATH_FLASH_DURATION)
+        {
+            Style::new().red()
+        } else {
+            Style::new()
+        };
+
+        let block = Block::bordered()
+            .title(title.centered())
+            .title_bottom(instructions.centered())
+            .border_set(self.border_set)
+            .border_style(border_style);
+
+        let log_text = self.last_log_line.as_deref().unwrap_or("").to_string();
+
+        let log_text = if self.last_log_error && self.invert_errors {
+            // Red text can be hard to read on some terminals
+            log_text.red().reversed()
+        } else if self.last_log_error {
+            log_text.red()
+        } else if self.terminal_background.is_some_and(terminal::is_light) {
+            // Yellow is hard to read on light backgrounds
+            log_text.blue()
+        } else {
+            log_text.yellow()
+        };
+
+        if self.stats_only {
+            let elapsed = self.started_at.elapsed().as_secs();
+            let frame_times = self.frame_times();
+            let frame_time_count = frame_times.len().max(1) as u32;
+            let (total, slowest) = frame_times.fold((Duration::ZERO, Duration::ZERO), |acc, t| {
+                (acc.0 + t, acc.1.max(t))
+            });
+            let stats = Text::from(vec![
+                Line::from(format!("FPS: {}", self.fps())),
+                Line::from(format!(
+                    "Frame time: {:.1}ms average, {:.1}ms slowest",
+                    (total / frame_time_count).as_secs_f32() * 1000.0,
+                    slowest.as_secs_f32() * 1000.0
+                )),
+                Line::from(format!("Frames: {}", self.frame_stats.total_frames())),
+                Line::from(format!("Tick: {}", self.game_tick())),
+                Line::from(format!("Time: {}:{:02}", elapsed / 60, elapsed % 60)),
+                Line::from(format!("Memory: {}", self.describe_memory())),
+                Line::from(format!("Dropped ticks: {}", self.dropped_ticks)),
+                Line::from(format!(
+                    "AR: {}",
+                    if self.aspect_correction { "on" } else { "off" }
+                )),
+                Line::from(self.describe_levels()),
+                Line::default(),
+                Line::from(log_text),
+            ]);
+
+            Paragraph::new(stats)
+                .centered()
+                .block(block)
+                .render(area, buf);
+            return;
+        }
+
+        let log_text = Text::from(log_text);
+        let inner_area = block.inner(area);
+
+        Paragraph::new(log_text)
+            .centered()
+            .block(block)
+            .render(area, buf);
+
+        if self.terminal_too_small(area) {
+            let message_area = Rect::new(
+                inner_area.x,
+                inner_area.y + inner_area.height / 2,
+                inner_area.width,
+                1.min(inner_area.height),
+            );
+            Paragraph::new("Terminal too small")
+                .centered()
+                .render(message_area, buf);
+            return;
+        }
+
+        let image_area = self.image_area(area);
+
+        // The image is fitted into the area, so it may not cover all of it.
+        // Filling the background first makes the letterboxing look intentional.
+        if let Some(background) = self.background {
+            buf.set_style(image_area, Style::new().bg(background));
+        }
+
+        if let Some(overlay) = &self.overlay {
+            // Instead of the image, since not all protocols let us draw
+            // over it
+            Clear.render(image_area, buf);
+            Paragraph::new(self.overlay_lines(overlay.kind))
+                .block(
+                    Block::bordered()
+                        .title(format!(" {} - Esc to close ", overlay.kind.title()).bold()),
+                )
+                .scroll((overlay.scroll, 0))
+                .render(image_area, buf);
+        } else if self.render_paused {
+            // Not all protocols let us draw over the image either
+            Clear.render(image_area, buf);
+            let keys = self.key_bindings.keys_for(AppAction::ToggleRenderPause);
+            let message = if keys.is_empty() {
+                "Rendering paused".to_string()
+            } else {
+                format!("Rendering paused - <{}> to resume", keys.join("/"))
+            };
+            let message_area = Rect::new(
+                image_area.x,
+                image_area.y + image_area.height / 2,
+                image_area.width,
+                1.min(image_area.height),
+            );
+            Paragraph::new(message).centered().render(message_area, buf);
+        } else if self.ascii {
+            if let Some(frame) = &self.ascii_frame {
+                Paragraph::new(self.ascii_renderer.render(frame, image_area))
+                    .centered()
+                    .render(image_area, buf);
+            }
+        } else if let Some(frame) = &self.current_frame {
+            // We may be asked to render before Doom has drawn anything
+            let frame_area = if self.fit_mode == FitMode::Center {
+                let cells = Backend::frame_area(frame);
+                let width = cells.width.min(image_area.width);
+                let height = cells.height.min(image_area.height);
+                Rect::new(
+                    image_area.x + (image_area.width - width) / 2,
+                    image_area.y + (image_area.height - height) / 2,
+                    width,
+                    height,
+                )
+            } else {
+                image_area
+            };
+            Backend::render(frame, frame_area, buf);
+        }
+
+        if self.debug_overlay {
+            self.render_debug_overlay(area, inner_area, image_area, buf);
+        }
+
+        if let Some(sent_events) = &self.sent_events {
+            render_sent_events(sent_events, image_area, buf);
+        }
+
+        if self.waiting_to_start {
+            let prompt = "Press any key to start";
+            let width = (prompt.len() as u16 + 2).min(image_area.width);
+            let prompt_area = Rect::new(
+                image_area.x + (image_area.width - width) / 2,
+                image_area.y + image_area.height / 2,
+                width,
+                1.min(image_area.height),
+            );
+            Paragraph::new(prompt)
+                .centered()
+                .style(Style::new().black().on_white())
+                .render(prompt_area, buf);
+        }
+
+        if let Some(line) = &self.console {
+            let console_area = Rect::new(
+                image_area.x,
+                image_area.bottom().saturating_sub(1),
+                image_area.width,
+                1.min(image_area.height),
+            );
+            Clear.render(console_area, buf);
+            Paragraph::new(format!("> {line}█"))
+                .style(Style::new().black().on_white())
+                .render(console_area, buf);
+        }
+
+        if let Some((notice, _)) = self
+            .state_notice
+            .as_ref()
+            .filter(|(_, at)| at.elapsed() < STATE_NOTICE_DURATION)
+        {
+            let width = (notice.len() as u16 + 2).min(image_area.width);
+            let notice_area = Rect::new(
+                image_area.x + (image_area.width - width) / 2,
+                image_area.y,
+                width,
+                1.min(image_area.height),
+            );
+            Paragraph::new(notice.as_str())
+                .centered()
+                .style(Style::new().black().on_white())
+                .render(notice_area, buf);
+        }
+    }
+}
+
+impl DoomApp {
+    /// How big WASM memory is, and how big it can get.
+    fn describe_memory(&self) -> String {
+        let mib = |pages: u32| pages as f64 * WASM_PAGE_SIZE as f64 / (1 << 20) as f64;
+        format!(
+            "{} of {} pages ({:.1} of {:.1} MiB)",
+            self.memory_pages,
+            self.max_memory_pages,
+            mib(self.memory_pages),
+            mib(self.max_memory_pages)
+        )
+    }
+
+    /// Shows the areas we render into, for debugging layout and scaling.
+    fn render_debug_overlay(
+        &self,
+        area: Rect,
+        inner_area: Rect,
+        image_area: Rect,
+        buf: &mut Buffer,
+    ) {
+        let describe =
+            |rect: Rect| format!("{}x{} at {},{}", rect.width, rect.height, rect.x, rect.y);
+
+        let image = match &self.current_frame {
+            _ if self.ascii => "ASCII".to_string(),
+            Some(frame) => {
+                let cells = Backend::frame_area(frame);
+                // How big each of Doom's pixels ends up on screen, going by
+                // the real font size rather than the zoomed one
+                let width = cells.width as u32 * self.default_font_size.0 as u32;
+                let scale = width as f32 / self.frame_size.0 as f32;
+                format!("{}x{} cells, {scale:.2}x", cells.width, cells.height)
+            }
+            None => "none yet".to_string(),
+        };
+
+        let lines = vec![
+            Line::from(format!("Terminal: {}", describe(area))),
+            Line::from(format!("Inner: {}", describe(inner_area))),
+            Line::from(format!("Image area: {}", describe(image_area))),
+            Line::from(format!("Image: {image}")),
+            Line::from(format!("Memory: {}", self.describe_memory())),
+            Line::from(format!(
+                "Render cache: {}",
+                match self.render_cache.hit_rate() {
+                    Some(rate) => format!("{:.0}% hits", rate * 100.0),
+                    None => "unused".to_string(),
+                }
+            )),
+        ];
+        let width = lines.iter().map(Line::width).max().unwrap_or(0) as u16;
+        let overlay_area = Rect::new(
+            image_area.x,
+            image_area.y,
+            width.min(image_area.width),
+            (lines.len() as u16).min(image_area.height),
+        );
+
+        Paragraph::new(lines)
+            .style(Style::new().black().on_white())
+            .render(overlay_area, buf);
+    }
+}
+
+/// Lists the last events sent to Doom in the top right corner of the image,
+/// newest at the bottom, for working out what a key really sends.
+fn render_sent_events(sent_events: &VecDeque<(i32, i32)>, image_area: Rect, buf: &mut Buffer) {
+    let lines: Vec<_> = sent_events
+        .iter()
+        .map(|&(kind, code)| {
+            let kind = match kind {
+                0 => "down".to_string(),
+                1 => "up".to_string(),
+                kind => kind.to_string(),
+            };
+            // Doom uses ASCII for the keys that have it
+            let printable = u8::try_from(code)
+                .ok()
+                .filter(u8::is_ascii_graphic)
+                .map(|ch| format!(" '{}'", ch as char))
+                .unwrap_or_default();
+            Line::from(format!("{kind} {code:#04x} ({code}){printable}"))
+        })
+        .collect();
+    if lines.is_empty() {
+        return;
+    }
+
+    let width = (lines.iter().map(Line::width).max().unwrap_or(0) as u16).min(image_area.width);
+    let overlay_area = Rect::new(
+        image_area.right() - width,
+        image_area.y,
+        width,
+        (lines.len() as u16).min(image_area.height),
+    );
+    Paragraph::new(lines)
+        .style(Style::new().black().on_white())
+        .render(overlay_area, buf);
+}
+
+/// The keys that move the camera in photo mode, standing in for their usual
+/// bindings: WASD to move and strafe, Q and E to turn.
+fn photo_mode_binding(key_code: KeyCode) -> Option<Binding> {
+    let code = match key_code {
+        KeyCode::Char('w') => 0xad,
+        KeyCode::Char('s') => 0xaf,
+        KeyCode::Char('a') => b',' as i32,
+        KeyCode::Char('d') => b'.' as i32,
+        KeyCode::Char('q') => 0xac,
+        KeyCode::Char('e') => 0xae,
+        _ => return None,
+    };
+    Some(Binding::Doom(code))
+}
+
+fn key_event_to_doom_event(key_event: KeyEventKind) -> Option<i32> {
+    match key_event {
+        KeyEventKind::Press => Some(0),
+        KeyEventKind::Release => Some(1),
+        KeyEventKind::Repeat => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headless_app(store: &mut Store, key_bindings: KeyBindings) -> DoomApp {
+        let memory = Memory::new(store, MemoryType::new(MEMORY_PAGES, None, false)).unwrap();
+        DoomApp::new(
+            &Options::default(),
+            Resources {
+                memory,
+                picker: Picker::from_fontsize(DEFAULT_FONT_SIZE),
+                terminal_background: None,
+                reports_releases: true,
+                scripted_input: false,
+                headless: true,
+                key_bindings,
+                lut: None,
+                pwads: Vec::new(),
+                palettes: None,
+                frame_dump: None,
+                frame_hashes: None,
+                render_thread: None,
+                snapshot: None,
+            },
+        )
+    }
+
+    fn solid_frame(color: [u8; 4]) -> Vec<u8> {
+        let (width, height) = DEFAULT_FRAME_SIZE;
+        color.repeat(width as usize * height as usize)
+    }
+
+    /// Does what a key press does once `handle_key` has found its binding.
+    fn press(app: &mut DoomApp, key_code: KeyCode) {
+        match app.key_bindings.get(key_code) {
+            Some(Binding::App(action)) => app.perform_action(action),
+            binding => panic!("{key_code:?} is bound to {binding:?}, not an app action"),
+        }
+    }
+
+    #[test]
+    fn presents_injected_frames() {
+        let mut store = Store::default();
+        let mut app = headless_app(&mut store, KeyBindings::default());
+
+        app.present_frame(Cow::Owned(solid_frame([255, 0, 0, 255])), None, true)
+            .unwrap();
+        assert!(app.current_frame.is_some());
+        assert_eq!(app.frame_stats.total_frames(), 1);
+
+        app.present_frame(Cow::Owned(solid_frame([0, 0, 255, 255])), None, true)
+            .unwrap();
+        assert_eq!(app.frame_stats.total_frames(), 2);
+    }
+
+    #[test]
+    fn rejects_frames_of_the_wrong_size() {
+        let mut store = Store::default();
+        let mut app = headless_app(&mut store, KeyBindings::default());
+
+        assert!(app
+            .present_frame(Cow::Owned(vec![0; 16]), None, true)
+            .is_err());
+        assert!(app.current_frame.is_none());
+        assert_eq!(app.frame_stats.total_frames(), 0);
+    }
+
+    #[test]
+    fn keys_change_how_frames_are_shown() {
+        let mut store = Store::default();
+        let bindings = KeyBindings::parse("f9 = render-pause\n").unwrap();
+        let mut app = headless_app(&mut store, bindings);
+        let area = Rect::new(0, 0, 80, 24);
+
+        press(&mut app, KeyCode::F(9));
+        app.present_frame(Cow::Owned(solid_frame([255; 4])), None, true)
+            .unwrap();
+        assert!(app.current_frame.is_none());
+        assert_eq!(app.frame_stats.total_frames(), 0);
+        assert!(app.render_to_string(area).contains("Rendering paused"));
+
+        // The frame missed while paused is drawn on the next one
+        press(&mut app, KeyCode::F(9));
+        app.present_frame(Cow::Owned(solid_frame([255; 4])), None, false)
+            .unwrap();
+        assert!(app.current_frame.is_some());
+        assert_eq!(app.frame_stats.total_frames(), 1);
+        assert!(!app.render_to_string(area).contains("Rendering paused"));
+    }
+
+    #[test]
+    fn cycles_to_ascii_after_the_image_protocols() {
+        let mut store = Store::default();
+        let mut app = headless_app(&mut store, KeyBindings::default());
+
+        while !app.ascii {
+            press(&mut app, KeyCode::Char('p'));
+        }
+        assert_eq!(app.protocol_name(), "ASCII");
+
+        app.present_frame(Cow::Owned(solid_frame([255; 4])), None, true)
+            .unwrap();
+        assert!(app.ascii_frame.is_some());
+        assert_eq!(app.frame_stats.total_frames(), 1);
+    }
+
+    /// Compares reading frames the way this build does with copying them
+    /// out of WASM memory, for the `zero-copy` feature. How long either
+    /// takes depends on the machine, so this only prints the numbers; run
+    /// it with `cargo test --release --features zero-copy -- --ignored
+    /// --nocapture read_framebuffer`.
+    #[test]
+    #[ignore]
+    fn bench_read_framebuffer() {
+        const FRAMES: u32 = 1000;
+
+        let mut store = Store::default();
+        let memory = Memory::new(&mut store, MemoryType::new(MEMORY_PAGES, None, false)).unwrap();
+        let view = memory.view(&store);
+        let (width, height) = DEFAULT_FRAME_SIZE;
+        let size = width as u64 * height as u64 * 4;
+
+        let time = |read: &dyn Fn() -> usize| {
+            let started = Instant::now();
+            for _ in 0..FRAMES {
+                std::hint::black_box(read());
+            }
+            started.elapsed() / FRAMES
+        };
+        let this_build = time(&|| {
+            let frame = read_framebuffer(&view, 0, DEFAULT_FRAME_SIZE, PixelFormat::Rgba).unwrap();
+            std::hint::black_box(&*frame).len()
+        });
+        let copied = time(&|| {
+            let frame = WasmSlice::new(&view, 0, size)
+                .and_then(|slice| slice.read_to_vec())
+                .unwrap();
+            std::hint::black_box(&*frame).len()
+        });
+
+        println!(
+            "{}: {this_build:?} a frame, copying: {copied:?} a frame",
+            if cfg!(feature = "zero-copy") {
+                "zero-copy"
+            } else {
+                "this build (also copying)"
+            }
+        );
     }
 }
@@ -0,0 +1,76 @@
+use anyhow::{bail, Context, Result};
+use ratatui::layout::Rect;
+
+/// Space left around the image, in cells, inside the border and below the
+/// log line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Margins {
+    pub top: u16,
+    pub right: u16,
+    pub bottom: u16,
+    pub left: u16,
+}
+
+impl Default for Margins {
+    /// One column on each side, to keep the image off the border, and
+    /// nothing above or below since the log line already separates the
+    /// image from the top border.
+    fn default() -> Self {
+        Self {
+            top: 0,
+            right: 1,
+            bottom: 0,
+            left: 1,
+        }
+    }
+}
+
+impl Margins {
+    /// Reads margins written like CSS: one value for all sides, two for
+    /// vertical and horizontal, or four for top, right, bottom and left.
+    pub fn parse(value: &str) -> Result<Self> {
+        let sides = value
+            .split(',')
+            .map(|side| side.trim().parse::<u16>())
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Invalid margins '{value}'"))?;
+        Ok(match sides[..] {
+            [all] => Self {
+                top: all,
+                right: all,
+                bottom: all,
+                left: all,
+            },
+            [vertical, horizontal] => Self {
+                top: vertical,
+                right: horizontal,
+                bottom: vertical,
+                left: horizontal,
+            },
+            [top, right, bottom, left] => Self {
+                top,
+                right,
+                bottom,
+                left,
+            },
+            _ => bail!("Invalid margins '{value}', expected 1, 2 or 4 numbers"),
+        })
+    }
+
+    /// Where the image goes in a terminal of the given size. The border
+    /// takes a cell on each side and the log line a row at the top; the
+    /// margins come out of what's left. If they don't fit, the area is
+    /// empty.
+    pub fn apply(&self, area: Rect) -> Rect {
+        let x = self.left.saturating_add(1);
+        let y = self.top.saturating_add(2);
+        Rect::new(
+            x,
+            y,
+            area.width
+                .saturating_sub(x.saturating_add(self.right).saturating_add(1)),
+            area.height
+                .saturating_sub(y.saturating_add(self.bottom).saturating_add(1)),
+        )
+    }
+}
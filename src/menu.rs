@@ -0,0 +1,77 @@
+use anyhow::{Context, Result};
+
+use crate::frame_hash;
+
+// About a second's worth of identical frames at Doom's 35 FPS
+const STATIC_FRAMES_FOR_MENU: u32 = 35;
+
+/// How we tell that the game is showing a menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuDetection {
+    /// The frame hasn't changed for a while. Works with any build, but
+    /// misses menus shown over something moving, like the title demos.
+    StaticFrames,
+    /// A flag in WASM memory, e.g. Doom's `menuactive`, is set.
+    Address(u32),
+}
+
+impl MenuDetection {
+    pub fn parse(value: &str) -> Result<Self> {
+        if value == "static" {
+            return Ok(Self::StaticFrames);
+        }
+        match value.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => value.parse(),
+        }
+        .map(Self::Address)
+        .with_context(|| {
+            format!("Invalid menu detection '{value}', expected `static` or an address")
+        })
+    }
+}
+
+/// Keeps track of whether the game is in a menu, so we can take it easy
+/// while it is.
+#[derive(Debug, Clone)]
+pub struct MenuDetector {
+    detection: MenuDetection,
+    last_hash: Option<u64>,
+    static_frames: u32,
+}
+
+impl MenuDetector {
+    pub fn new(detection: MenuDetection) -> Self {
+        Self {
+            detection,
+            last_hash: None,
+            static_frames: 0,
+        }
+    }
+
+    /// The address of the menu flag to read for `update`, if there is one.
+    pub fn address(&self) -> Option<u32> {
+        match self.detection {
+            MenuDetection::Address(address) => Some(address),
+            MenuDetection::StaticFrames => None,
+        }
+    }
+
+    /// Takes in a new frame, along with the menu flag if we're reading one,
+    /// and returns whether we're in a menu.
+    pub fn update(&mut self, frame: &[u8], menu_flag: Option<i32>) -> bool {
+        match self.detection {
+            MenuDetection::Address(_) => menu_flag.is_some_and(|flag| flag != 0),
+            MenuDetection::StaticFrames => {
+                let hash = frame_hash::hash(frame);
+                if self.last_hash == Some(hash) {
+                    self.static_frames = self.static_frames.saturating_add(1);
+                } else {
+                    self.static_frames = 0;
+                }
+                self.last_hash = Some(hash);
+                self.static_frames >= STATIC_FRAMES_FOR_MENU
+            }
+        }
+    }
+}
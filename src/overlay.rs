@@ -0,0 +1,52 @@
+use ratatui::crossterm::event::KeyCode;
+
+/// Panels shown over the game that take over the keyboard while they're
+/// open, so scrolling them doesn't move the player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayKind {
+    /// What each key does.
+    Help,
+    /// Recent log lines, newest first.
+    Log,
+}
+
+impl OverlayKind {
+    pub fn title(self) -> &'static str {
+        match self {
+            Self::Help => "Help",
+            Self::Log => "Log",
+        }
+    }
+}
+
+/// An open overlay, and how far it's scrolled.
+#[derive(Debug, Clone)]
+pub struct Overlay {
+    pub kind: OverlayKind,
+    pub scroll: u16,
+}
+
+impl Overlay {
+    pub fn new(kind: OverlayKind) -> Self {
+        Self { kind, scroll: 0 }
+    }
+
+    /// Scrolls in response to a key press, given how many lines there are
+    /// and how many fit on a page. Returns false if the key closes the
+    /// overlay instead. Other keys do nothing.
+    pub fn handle_key(&mut self, key: KeyCode, lines: usize, page: u16) -> bool {
+        let max_scroll = u16::try_from(lines.saturating_sub(1)).unwrap_or(u16::MAX);
+        self.scroll = match key {
+            KeyCode::Esc => return false,
+            KeyCode::Up => self.scroll.saturating_sub(1),
+            KeyCode::Down => self.scroll.saturating_add(1),
+            KeyCode::PageUp => self.scroll.saturating_sub(page),
+            KeyCode::PageDown => self.scroll.saturating_add(page),
+            KeyCode::Home => 0,
+            KeyCode::End => max_scroll,
+            _ => self.scroll,
+        }
+        .min(max_scroll);
+        true
+    }
+}
@@ -0,0 +1,69 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+
+use crate::wad::{self, Pwad};
+
+/// The size of one palette in a PLAYPAL lump: 256 RGB colors.
+const PALETTE_SIZE: usize = 256 * 3;
+
+/// The palettes from a WAD's PLAYPAL lump, for builds that draw indexed
+/// color and leave turning it into RGB to us.
+///
+/// Doom switches between them for effects: 0 is the normal one, 1 to 8
+/// tint red as the player takes damage, 9 to 12 flash yellow on pickups and
+/// 13 tints green in a radiation suit.
+#[derive(Debug, Clone)]
+pub struct Palettes {
+    playpal: Vec<u8>,
+}
+
+impl Palettes {
+    pub fn from_playpal(playpal: &[u8]) -> Result<Self> {
+        if playpal.is_empty() || playpal.len() % PALETTE_SIZE != 0 {
+            bail!(
+                "PLAYPAL is {} bytes, expected a multiple of {PALETTE_SIZE}",
+                playpal.len()
+            );
+        }
+        Ok(Self {
+            playpal: playpal.to_vec(),
+        })
+    }
+
+    /// Reads the palettes from any WAD, including an IWAD.
+    pub fn load(path: &Path) -> Result<Self> {
+        let data =
+            fs::read(path).with_context(|| format!("Failed to read WAD {}", path.display()))?;
+        if !matches!(data.get(..4), Some(b"IWAD" | b"PWAD")) {
+            bail!("{} isn't a WAD file", path.display());
+        }
+        let playpal = wad::find_lump(&data, "PLAYPAL")
+            .with_context(|| format!("{} has no PLAYPAL lump", path.display()))?;
+        Self::from_playpal(playpal)
+            .with_context(|| format!("Invalid PLAYPAL in {}", path.display()))
+    }
+
+    /// The palettes from the last PWAD that has any, the way Doom would
+    /// pick them.
+    pub fn from_pwads(pwads: &[Pwad]) -> Option<Result<Self>> {
+        pwads.iter().rev().find_map(|pwad| {
+            let playpal = pwad.lump("PLAYPAL")?;
+            Some(
+                Self::from_playpal(playpal)
+                    .with_context(|| format!("Invalid PLAYPAL in {}", pwad.name())),
+            )
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        self.playpal.len() / PALETTE_SIZE
+    }
+
+    /// One palette, as 256 RGB colors. Out of range indices get the normal
+    /// palette.
+    pub fn get(&self, index: usize) -> &[u8] {
+        let index = if index < self.count() { index } else { 0 };
+        &self.playpal[index * PALETTE_SIZE..(index + 1) * PALETTE_SIZE]
+    }
+}
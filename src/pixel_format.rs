@@ -0,0 +1,62 @@
+use std::borrow::Cow;
+
+use anyhow::{bail, Result};
+
+/// How a Doom build lays out the pixels in its framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PixelFormat {
+    /// 4 bytes per pixel, which is what the bundled module draws.
+    #[default]
+    Rgba,
+    /// 3 bytes per pixel, with no alpha.
+    Rgb,
+    /// 1 byte per pixel, indexing into Doom's palette.
+    Indexed,
+}
+
+impl PixelFormat {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "rgba" => Ok(Self::Rgba),
+            "rgb" => Ok(Self::Rgb),
+            "indexed" => Ok(Self::Indexed),
+            _ => bail!("Unknown pixel format '{value}', expected rgba, rgb or indexed"),
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> u64 {
+        match self {
+            Self::Rgba => 4,
+            Self::Rgb => 3,
+            Self::Indexed => 1,
+        }
+    }
+
+    /// Turns a frame in this format into RGBA, which is what everything
+    /// after reading the framebuffer works with. RGBA frames are passed
+    /// through as they are. Indexed frames are looked up in `palette`, 256
+    /// RGB colors, or shown as grayscale without one.
+    pub fn to_rgba<'a>(self, frame: Cow<'a, [u8]>, palette: Option<&[u8]>) -> Cow<'a, [u8]> {
+        match self {
+            Self::Rgba => frame,
+            Self::Rgb => Cow::Owned(
+                frame
+                    .chunks_exact(3)
+                    .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+                    .collect(),
+            ),
+            Self::Indexed => Cow::Owned(
+                frame
+                    .iter()
+                    .flat_map(|&index| match palette {
+                        Some(palette) => {
+                            let color = &palette[index as usize * 3..][..3];
+                            [color[0], color[1], color[2], 255]
+                        }
+                        None => [index, index, index, 255],
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use anyhow::{bail, Context, Result};
+
+use crate::{cli::PROTOCOL_NAMES, color_filter::ColorFilter, fit::FitAxis};
+
+/// A named set of view settings to switch to in one go. Settings a preset
+/// leaves out stay as they are.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewPreset {
+    pub name: String,
+    /// A lowercase protocol name, including `ascii`.
+    pub protocol: Option<String>,
+    pub zoom: Option<PresetZoom>,
+    pub fit_axis: Option<FitAxis>,
+    /// `Some(None)` turns the color filter off.
+    pub color_filter: Option<Option<ColorFilter>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresetZoom {
+    Auto,
+    Fixed(u16),
+}
+
+impl ViewPreset {
+    /// Reads a preset written like `name:protocol=kitty,zoom=2`, with any
+    /// of `protocol`, `zoom` (a number or `auto`), `fit` and `filter` (a
+    /// color filter or `none`).
+    pub fn parse(value: &str) -> Result<Self> {
+        let Some((name, settings)) = value.split_once(':') else {
+            bail!("Invalid view preset '{value}', expected e.g. big:protocol=kitty,zoom=2");
+        };
+        if name.is_empty() {
+            bail!("View preset '{value}' has no name");
+        }
+
+        let mut preset = Self {
+            name: name.to_string(),
+            protocol: None,
+            zoom: None,
+            fit_axis: None,
+            color_filter: None,
+        };
+        for setting in settings.split(',').map(str::trim) {
+            let Some((key, value)) = setting.split_once('=') else {
+                bail!("Invalid setting '{setting}' in view preset {name}, expected key=value");
+            };
+            match key {
+                "protocol" => {
+                    let protocol = value.to_ascii_lowercase();
+                    if !PROTOCOL_NAMES.contains(&protocol.as_str()) {
+                        bail!(
+                            "Unknown protocol '{value}' in view preset {name}, expected one of {}",
+                            PROTOCOL_NAMES.join(", ")
+                        );
+                    }
+                    preset.protocol = Some(protocol);
+                }
+                "zoom" => {
+                    preset.zoom = Some(match value {
+                        "auto" => PresetZoom::Auto,
+                        _ => match value.parse() {
+                            Ok(zoom @ 1..) => PresetZoom::Fixed(zoom),
+                            _ => bail!(
+                                "Invalid zoom '{value}' in view preset {name}, expected a \
+                                 number from 1 or auto"
+                            ),
+                        },
+                    })
+                }
+                "fit" => {
+                    preset.fit_axis = Some(
+                        FitAxis::parse(value)
+                            .with_context(|| format!("Invalid view preset {name}"))?,
+                    )
+                }
+                "filter" => {
+                    preset.color_filter = Some(match value {
+                        "none" => None,
+                        _ => Some(
+                            ColorFilter::parse(value)
+                                .with_context(|| format!("Invalid view preset {name}"))?,
+                        ),
+                    })
+                }
+                _ => bail!(
+                    "Unknown setting '{key}' in view preset {name}, expected protocol, zoom, \
+                     fit or filter"
+                ),
+            }
+        }
+        Ok(preset)
+    }
+}
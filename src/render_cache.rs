@@ -0,0 +1,62 @@
+/// Remembers what the last encoded frame was, so an identical frame can
+/// reuse it instead of being encoded again. Static screens like menus and
+/// pauses redraw the same thing over and over, and encoding is the slowest
+/// part of rendering.
+///
+/// The key is the frame's content plus whatever else changes how it's
+/// encoded, like the protocol and zoom.
+#[derive(Debug)]
+pub struct RenderCache<K> {
+    last: Option<K>,
+    hits: u64,
+    misses: u64,
+}
+
+impl<K: PartialEq> Default for RenderCache<K> {
+    fn default() -> Self {
+        Self {
+            last: None,
+            hits: 0,
+            misses: 0,
+        }
+    }
+}
+
+impl<K: PartialEq> RenderCache<K> {
+    /// Whether the last encoded frame had this key, so it can be reused.
+    /// Otherwise, the frame is about to be encoded and its key remembered.
+    pub fn check(&mut self, key: K) -> bool {
+        if self.last.as_ref() == Some(&key) {
+            self.hits += 1;
+            true
+        } else {
+            self.misses += 1;
+            self.last = Some(key);
+            false
+        }
+    }
+
+    /// Forgets the last frame, for when something else was shown since.
+    pub fn clear(&mut self) {
+        self.last = None;
+    }
+
+    /// The share of frames that were reused, if there were any.
+    pub fn hit_rate(&self) -> Option<f32> {
+        let total = self.hits + self.misses;
+        (total > 0).then(|| self.hits as f32 / total as f32)
+    }
+}
+
+/// A quick hash of a frame's content. Unlike `frame_hash::hash`, it only
+/// needs to be consistent within a run, so it goes 8 bytes at a time.
+pub fn hash(bytes: &[u8]) -> u64 {
+    let chunks = bytes.chunks_exact(8);
+    let rest = chunks.remainder();
+    chunks
+        .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap_or_default()))
+        .chain(rest.iter().map(|&byte| byte as u64))
+        .fold(0xcbf2_9ce4_8422_2325, |hash, word| {
+            (hash ^ word).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+}
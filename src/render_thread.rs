@@ -0,0 +1,86 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use anyhow::{anyhow, Context, Result};
+use ratatui::{buffer::Buffer, layout::Rect, DefaultTerminal};
+
+/// Owns the terminal on a thread of its own, so slow terminal output doesn't
+/// hold up the game. The game thread still renders the UI, but into an
+/// off-screen buffer, which is sent here to be written out.
+pub struct RenderThread {
+    sender: SyncSender<Buffer>,
+    area: Arc<Mutex<Rect>>,
+    handle: JoinHandle<Result<()>>,
+}
+
+impl RenderThread {
+    pub fn new(mut terminal: DefaultTerminal) -> Result<Self> {
+        let size = terminal.size().context("Failed to get the terminal size")?;
+        let area = Arc::new(Mutex::new(Rect::new(0, 0, size.width, size.height)));
+
+        // Room for one frame in flight while another is being drawn. If the
+        // terminal falls further behind than that, frames are dropped.
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let thread_area = area.clone();
+        let handle = thread::spawn(move || draw_frames(&mut terminal, receiver, &thread_area));
+
+        Ok(Self {
+            sender,
+            area,
+            handle,
+        })
+    }
+
+    /// The area to render the next frame into.
+    pub fn area(&self) -> Rect {
+        *self.area.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Queues a frame to be drawn, unless the terminal is still busy with
+    /// earlier ones.
+    pub fn send(&self, frame: Buffer) -> Result<()> {
+        match self.sender.try_send(frame) {
+            Ok(()) | Err(TrySendError::Full(_)) => Ok(()),
+            Err(TrySendError::Disconnected(_)) => Err(anyhow!("The render thread stopped")),
+        }
+    }
+
+    /// Waits for the frames that are already queued to be drawn, and returns
+    /// the error that stopped the thread, if any.
+    pub fn finish(self) -> Result<()> {
+        drop(self.sender);
+        self.handle
+            .join()
+            .map_err(|_| anyhow!("The render thread panicked"))?
+    }
+}
+
+fn draw_frames(
+    terminal: &mut DefaultTerminal,
+    receiver: Receiver<Buffer>,
+    area: &Mutex<Rect>,
+) -> Result<()> {
+    while let Ok(mut frame) = receiver.recv() {
+        // Only the newest frame is worth drawing
+        if let Some(newer) = receiver.try_iter().last() {
+            frame = newer;
+        }
+
+        let completed = terminal
+            .draw(|target| {
+                // If the terminal was resized after the frame was rendered,
+                // it doesn't fit anymore; the next one will
+                if target.area() == frame.area {
+                    target.buffer_mut().content = frame.content;
+                }
+            })
+            .context("Failed to draw to the terminal")?;
+        *area.lock().unwrap_or_else(|e| e.into_inner()) = completed.area;
+    }
+    Ok(())
+}
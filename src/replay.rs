@@ -0,0 +1,95 @@
+use std::{collections::VecDeque, ops::Deref};
+
+use image::{imageops::FilterType, ImageBuffer, Rgba, RgbaImage};
+
+/// Keeps the most recent frames, scaled down, for instant replay.
+///
+/// The buffer is sized from a memory budget rather than a frame count, since
+/// that's easier to pick: the number of frames that fit follows from how
+/// far they're scaled down.
+#[derive(Debug, Clone)]
+pub struct ReplayBuffer {
+    budget: u64,
+    divisor: u32,
+    // The size of the frames coming in, before scaling down
+    frame_size: (u32, u32),
+    capacity: usize,
+    frames: VecDeque<RgbaImage>,
+}
+
+impl ReplayBuffer {
+    /// A buffer holding as many frames, divided in size by `divisor` each
+    /// way, as fit in `budget` bytes. It starts out sized for 640x400
+    /// frames, and is sized again when frames of another size come in.
+    pub fn new(budget: u64, divisor: u32) -> Self {
+        let mut buffer = Self {
+            budget,
+            divisor,
+            frame_size: (0, 0),
+            capacity: 0,
+            frames: VecDeque::new(),
+        };
+        buffer.set_frame_size((640, 400));
+        buffer
+    }
+
+    /// Sizes the buffer for frames of a new size. The frames kept so far
+    /// are a different size, and are dropped.
+    fn set_frame_size(&mut self, frame_size: (u32, u32)) {
+        let (width, height) = self.scaled_size(frame_size);
+        self.frame_size = frame_size;
+        self.capacity = (self.budget / (width as u64 * height as u64 * 4)) as usize;
+        self.frames.clear();
+    }
+
+    fn scaled_size(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        (
+            (width / self.divisor).max(1),
+            (height / self.divisor).max(1),
+        )
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn push<C: Deref<Target = [u8]>>(&mut self, frame: &ImageBuffer<Rgba<u8>, C>) {
+        if frame.dimensions() != self.frame_size {
+            self.set_frame_size(frame.dimensions());
+        }
+        if self.capacity == 0 {
+            return;
+        }
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        // Nearest neighbor, since this happens on every frame and the
+        // frames are only for a quick look back
+        let (width, height) = self.scaled_size(self.frame_size);
+        self.frames.push_back(image::imageops::resize(
+            frame,
+            width,
+            height,
+            FilterType::Nearest,
+        ));
+    }
+
+    /// A frame, oldest first, scaled back up to full size for display.
+    pub fn frame(&self, index: usize) -> Option<RgbaImage> {
+        let frame = self.frames.get(index)?;
+        Some(image::imageops::resize(
+            frame,
+            frame.width() * self.divisor,
+            frame.height() * self.divisor,
+            FilterType::Nearest,
+        ))
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context, Result};
+
+const MAGIC: &[u8; 8] = b"DOOMSTAT";
+const HEADER_SIZE: usize = MAGIC.len() + 8 + 8;
+
+/// A snapshot of the whole game, taken between ticks.
+///
+/// Doom keeps all its state in WASM memory, and between calls into it the
+/// stack is empty, so a copy of the memory is all it takes to restore the
+/// game exactly. Along with it, we keep the game clock, so Doom doesn't try
+/// to catch up on the time that passed since.
+///
+/// Files start with a magic number, then the game time in milliseconds and
+/// the time the state was saved in seconds since the Unix epoch, both as
+/// little endian `u64`s, then the memory.
+pub struct SaveState {
+    pub game_time: Duration,
+    pub saved_at: SystemTime,
+    pub memory: Vec<u8>,
+}
+
+impl SaveState {
+    pub fn path(dir: &Path, slot: u8) -> PathBuf {
+        dir.join(format!("slot{slot}.state"))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let saved_at = self
+            .saved_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+
+        let mut data = Vec::with_capacity(HEADER_SIZE + self.memory.len());
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&(self.game_time.as_millis() as u64).to_le_bytes());
+        data.extend_from_slice(&saved_at.to_le_bytes());
+        data.extend_from_slice(&self.memory);
+
+        fs::write(path, data)
+            .with_context(|| format!("Failed to write save state to {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read(path)
+            .with_context(|| format!("Failed to read save state from {}", path.display()))?;
+        if data.len() < HEADER_SIZE || !data.starts_with(MAGIC) {
+            bail!("{} isn't a save state", path.display());
+        }
+
+        let number = |at: usize| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&data[at..at + 8]);
+            u64::from_le_bytes(bytes)
+        };
+        let game_time = Duration::from_millis(number(MAGIC.len()));
+        let saved_at = SystemTime::UNIX_EPOCH + Duration::from_secs(number(MAGIC.len() + 8));
+
+        Ok(Self {
+            game_time,
+            saved_at,
+            memory: data[HEADER_SIZE..].to_vec(),
+        })
+    }
+
+    /// A short description, like "game time 3:25, saved 2 minutes ago".
+    pub fn describe(&self) -> String {
+        let game_seconds = self.game_time.as_secs();
+        let age = self.saved_at.elapsed().unwrap_or_default().as_secs();
+        let age = match age {
+            0..60 => "just now".to_string(),
+            60..3600 => format!("{} minutes ago", age / 60),
+            3600..86400 => format!("{} hours ago", age / 3600),
+            _ => format!("{} days ago", age / 86400),
+        };
+        format!(
+            "game time {}:{:02}, saved {age}",
+            game_seconds / 60,
+            game_seconds % 60
+        )
+    }
+}
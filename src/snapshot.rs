@@ -0,0 +1,66 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use ratatui::{buffer::Buffer, layout::Rect};
+
+/// A run without a terminal, for regression tests in CI: the app renders
+/// into a buffer of a fixed size, quits after a fixed number of ticks and
+/// saves the final screen, text and styles, to a file. Checked in, that file
+/// becomes the golden later runs are compared against.
+#[derive(Debug)]
+pub struct Snapshot {
+    pub path: PathBuf,
+    pub area: Rect,
+    pub ticks: u128,
+    /// Compare with the file instead of writing it.
+    pub check: bool,
+}
+
+impl Snapshot {
+    /// Saves the final screen, or checks it against the saved one. If it
+    /// doesn't match, the new one is written next to the old one with a
+    /// `.new` extension, and the first difference is returned.
+    pub fn finish(&self, buffer: &Buffer) -> Result<Option<String>> {
+        // The same format as ratatui's own test backend: the text one row per
+        // line, followed by every change of style
+        let actual = format!("{buffer:?}\n");
+
+        if !self.check {
+            fs::write(&self.path, actual)
+                .with_context(|| format!("Failed to write snapshot to {}", self.path.display()))?;
+            return Ok(None);
+        }
+
+        let expected = fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read snapshot from {}", self.path.display()))?;
+        if expected == actual {
+            return Ok(None);
+        }
+
+        let mut new_path = self.path.clone().into_os_string();
+        new_path.push(".new");
+        fs::write(&new_path, &actual).with_context(|| {
+            format!("Failed to write snapshot to {}", new_path.to_string_lossy())
+        })?;
+
+        let mut expected_lines = expected.lines();
+        let mut actual_lines = actual.lines();
+        let mut line = 1;
+        loop {
+            match (expected_lines.next(), actual_lines.next()) {
+                (Some(expected), Some(actual)) if expected == actual => line += 1,
+                (expected, actual) => {
+                    return Ok(Some(format!(
+                        "Screen doesn't match the snapshot in {} at line {line}:\n\
+                         expected: {}\n  actual: {}\n\
+                         The new screen is in {}",
+                        self.path.display(),
+                        expected.unwrap_or("<end>"),
+                        actual.unwrap_or("<end>"),
+                        new_path.to_string_lossy()
+                    )))
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+
+/// What's written to the status file.
+#[derive(Debug, Clone)]
+pub struct Status {
+    pub fps: u16,
+    pub tick: u128,
+    pub elapsed: Duration,
+    pub protocol: String,
+    pub zoom: u16,
+}
+
+impl Status {
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"fps\":{},\"tick\":{},\"elapsed_secs\":{:.3},\"protocol\":\"{}\",\"zoom\":{}}}\n",
+            self.fps,
+            self.tick,
+            self.elapsed.as_secs_f64(),
+            escape(&self.protocol),
+            self.zoom
+        )
+    }
+}
+
+/// Keeps a JSON file up to date with the game's status, for monitoring
+/// tools to read instead of scraping the screen.
+///
+/// The file is written to a temporary file next to it and renamed over it,
+/// so readers never see half of it.
+#[derive(Debug)]
+pub struct StatusFile {
+    path: PathBuf,
+    interval: Duration,
+    last_write: Option<Instant>,
+}
+
+impl StatusFile {
+    pub fn new(path: PathBuf, interval: Duration) -> Self {
+        Self {
+            path,
+            interval,
+            last_write: None,
+        }
+    }
+
+    /// Whether it's been long enough since the last write to write again.
+    pub fn is_due(&self) -> bool {
+        self.last_write
+            .is_none_or(|at| at.elapsed() >= self.interval)
+    }
+
+    pub fn write(&mut self, status: &Status) -> Result<()> {
+        self.last_write = Some(Instant::now());
+
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".tmp");
+        fs::write(&temp_path, status.to_json())
+            .with_context(|| format!("Failed to write status to {}", self.path.display()))?;
+        fs::rename(&temp_path, &self.path)
+            .with_context(|| format!("Failed to write status to {}", self.path.display()))
+    }
+}
+
+/// Escapes a string for a JSON string literal.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            ch if ch.is_control() => escaped.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,139 @@
+#[cfg(unix)]
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use ratatui::style::Color;
+
+#[cfg(unix)]
+const QUERY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Which image protocols the terminal says it supports.
+#[derive(Debug, Clone, Copy)]
+pub struct GraphicsSupport {
+    pub kitty: bool,
+    pub sixel: bool,
+}
+
+/// Asks the terminal for its background color. The terminal must already be
+/// in raw mode.
+pub fn query_background_color() -> Option<Color> {
+    parse_background_response(&query(b"\x1b]11;?\x1b\\")?)
+}
+
+/// Asks the terminal which image protocols it supports. Kitty's answers a
+/// query about a tiny image, and Sixel support is in the device attributes;
+/// iTerm2's can't be asked about. The terminal must already be in raw mode.
+pub fn query_graphics_support() -> Option<GraphicsSupport> {
+    let response = query(b"\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\")?;
+    Some(GraphicsSupport {
+        kitty: response.contains("\x1b_Gi=31;OK"),
+        sixel: device_attributes(&response).any(|attribute| attribute == "4"),
+    })
+}
+
+/// Sends a query and returns everything the terminal answered.
+///
+/// Not all terminals answer every query, so it's followed by a device
+/// attributes query which they all do answer; once we see that answer, we
+/// know there's nothing more coming.
+#[cfg(unix)]
+fn query(request: &[u8]) -> Option<String> {
+    let mut stdout = io::stdout();
+    stdout.write_all(request).ok()?;
+    stdout.write_all(b"\x1b[c").ok()?;
+    stdout.flush().ok()?;
+
+    let response = read_response()?;
+    Some(String::from_utf8_lossy(&response).into_owned())
+}
+
+/// Without a way to wait for stdin, a terminal that doesn't answer would
+/// leave a read hanging on it, taking later key presses, so we don't ask.
+#[cfg(not(unix))]
+fn query(_request: &[u8]) -> Option<String> {
+    None
+}
+
+/// Reads the answers up to the end of the device attributes one, waiting
+/// for each byte so a terminal that doesn't answer can't hang us. Nothing
+/// is left reading stdin after we give up, and nothing past the answers is
+/// read, so key presses and the answers to later queries stay where they
+/// are.
+#[cfg(unix)]
+fn read_response() -> Option<Vec<u8>> {
+    use std::os::fd::AsRawFd;
+
+    // Read byte by byte, straight from the file descriptor; `Stdin` would
+    // buffer whatever comes after
+    let fd = io::stdin().as_raw_fd();
+    let deadline = Instant::now() + QUERY_TIMEOUT;
+    let mut response = vec![];
+    while !is_device_attributes_end(&response) {
+        let timeout = deadline.checked_duration_since(Instant::now())?;
+        let mut poll_fd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: `poll_fd` is a single, initialized `pollfd`
+        let ready = unsafe { libc::poll(&mut poll_fd, 1, timeout.as_millis() as libc::c_int) };
+        if ready <= 0 {
+            return None;
+        }
+
+        let mut byte = 0u8;
+        // SAFETY: `byte` has room for the one byte we ask for
+        let read = unsafe { libc::read(fd, (&mut byte as *mut u8).cast(), 1) };
+        if read != 1 {
+            return None;
+        }
+        response.push(byte);
+    }
+    Some(response)
+}
+
+// The device attributes answer looks like `ESC [ ? ... c`
+#[cfg(unix)]
+fn is_device_attributes_end(response: &[u8]) -> bool {
+    response.ends_with(b"c") && response.windows(3).any(|window| window == b"\x1b[?")
+}
+
+// The device attributes, e.g. `4` for Sixel support, from the end of
+// the answers
+fn device_attributes(response: &str) -> impl Iterator<Item = &str> {
+    let start = response
+        .rfind("\x1b[?")
+        .map_or(response.len(), |index| index + "\x1b[?".len());
+    response[start..].trim_end_matches('c').split(';')
+}
+
+// The answer looks like `ESC ] 11 ; rgb:RRRR/GGGG/BBBB ESC \`, where each
+// component has 1 to 4 hex digits
+fn parse_background_response(response: &str) -> Option<Color> {
+    let start = response.find("]11;rgb:")? + "]11;rgb:".len();
+    let end = response[start..].find(['\x1b', '\x07'])? + start;
+
+    let mut components = response[start..end].split('/').map(|component| {
+        let value = u32::from_str_radix(component, 16).ok()?;
+        let max = (1u32 << (4 * component.len().clamp(1, 4))) - 1;
+        Some((value * 255 / max) as u8)
+    });
+
+    let (r, g, b) = (
+        components.next()??,
+        components.next()??,
+        components.next()??,
+    );
+    Some(Color::Rgb(r, g, b))
+}
+
+/// Whether text should be dark to be readable on this background.
+pub fn is_light(color: Color) -> bool {
+    match color {
+        Color::Rgb(r, g, b) => 299 * r as u32 + 587 * g as u32 + 114 * b as u32 > 128_000,
+        Color::White | Color::Gray => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+/// A patch WAD to load on top of the game's IWAD, e.g. a community map.
+#[derive(Debug, Clone)]
+pub struct Pwad {
+    pub path: PathBuf,
+    pub data: Vec<u8>,
+}
+
+impl Pwad {
+    /// Reads a PWAD, checking that it really is one.
+    pub fn load(path: &Path) -> Result<Self> {
+        let data =
+            fs::read(path).with_context(|| format!("Failed to read PWAD {}", path.display()))?;
+        match data.get(..4) {
+            Some(b"PWAD") => {}
+            Some(b"IWAD") => bail!(
+                "{} is an IWAD, not a PWAD; only patch WADs can be loaded on top of the game",
+                path.display()
+            ),
+            _ => bail!("{} isn't a WAD file", path.display()),
+        }
+        if data.len() > i32::MAX as usize {
+            bail!("{} is too big to load", path.display());
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            data,
+        })
+    }
+
+    /// The data of the last lump with this name, like Doom would use.
+    pub fn lump(&self, name: &str) -> Option<&[u8]> {
+        find_lump(&self.data, name)
+    }
+
+    /// The file name, for messages.
+    pub fn name(&self) -> String {
+        self.path.file_name().map_or_else(
+            || self.path.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        )
+    }
+}
+
+/// Finds the last lump with the given name in a WAD file's directory. Names
+/// are up to 8 characters, padded with zeros.
+pub fn find_lump<'a>(data: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    let read_u32 = |offset: usize| {
+        let bytes = data.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes(bytes.try_into().ok()?) as usize)
+    };
+    let lump_count = read_u32(4)?;
+    let directory = read_u32(8)?;
+
+    (0..lump_count).rev().find_map(|index| {
+        let entry = directory + index * 16;
+        let entry_name = data.get(entry + 8..entry + 16)?;
+        let entry_name = entry_name.split(|&b| b == 0).next()?;
+        if !entry_name.eq_ignore_ascii_case(name.as_bytes()) {
+            return None;
+        }
+        let (offset, size) = (read_u32(entry)?, read_u32(entry + 4)?);
+        data.get(offset..offset.checked_add(size)?)
+    })
+}
@@ -0,0 +1,51 @@
+use std::{
+    process,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use ratatui::crossterm::terminal::disable_raw_mode;
+
+/// Watches terminal draws, and gives up on the terminal if one doesn't
+/// finish in time, e.g. because an SSH connection froze. Without it, the
+/// draw would block forever and so would we.
+///
+/// The main thread is the one that's stuck, so the watchdog can't just ask
+/// it to exit; instead, it takes the terminal out of raw mode and exits the
+/// process itself. Leaving the alternate screen needs a write to the
+/// terminal, which is what's hanging in the first place, so that's skipped.
+pub struct DrawWatchdog {
+    draw_started: Arc<Mutex<Option<Instant>>>,
+}
+
+impl DrawWatchdog {
+    pub fn new(timeout: Duration) -> Self {
+        let draw_started = Arc::new(Mutex::new(None::<Instant>));
+
+        let watched = draw_started.clone();
+        thread::spawn(move || loop {
+            thread::sleep((timeout / 4).min(Duration::from_millis(100)));
+
+            let started = *watched.lock().unwrap_or_else(|e| e.into_inner());
+            if started.is_some_and(|started| started.elapsed() >= timeout) {
+                _ = disable_raw_mode();
+                eprintln!(
+                    "The terminal didn't respond for {}ms, exiting",
+                    timeout.as_millis()
+                );
+                process::exit(1);
+            }
+        });
+
+        Self { draw_started }
+    }
+
+    pub fn draw_started(&self) {
+        *self.draw_started.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+    }
+
+    pub fn draw_finished(&self) {
+        *self.draw_started.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
@@ -0,0 +1,24 @@
+use anyhow::{bail, Result};
+
+/// How zooming makes the image bigger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ZoomStrategy {
+    /// Pretend the font is smaller, so the same image covers more cells.
+    /// Cheap, but some terminals draw images at their own size whatever we
+    /// think the font size is.
+    #[default]
+    Font,
+    /// Scale the image up before encoding it, which works everywhere but
+    /// makes encoding slower.
+    Scale,
+}
+
+impl ZoomStrategy {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "font" => Ok(Self::Font),
+            "scale" => Ok(Self::Scale),
+            _ => bail!("Unknown zoom strategy '{value}', expected font or scale"),
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Runs the game without a terminal, as CI would, and compares the final
+//! screen with the goldens in `tests/snapshots`.
+
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// Runs `<name>.input` as scripted input in snapshot mode and checks the
+/// screen against `<name>.txt`. Without a golden, the run records one and
+/// the test fails, so it gets looked at before it's committed.
+fn check_snapshot(name: &str, args: &[&str]) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+    let golden = dir.join(format!("{name}.txt"));
+    let script = std::fs::read(dir.join(format!("{name}.input"))).unwrap();
+    let recording = !golden.exists();
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_wasm-doom-tui"));
+    command
+        .arg("--snapshot")
+        .arg(&golden)
+        .arg("--keys")
+        .arg(dir.join("keys.txt"))
+        .args(["--seed", "1"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if !recording {
+        command.arg("--snapshot-check");
+    }
+
+    let mut child = command.spawn().unwrap();
+    // Closing stdin ends the script; the run goes on to its last tick
+    child.stdin.take().unwrap().write_all(&script).unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(
+        !recording,
+        "Recorded a new snapshot in {}, check it and commit it",
+        golden.display()
+    );
+}
+
+#[test]
+fn title_screen() {
+    // Doom shows the title screen for a little under 5 seconds before the
+    // demos start, so it looks the same however the ticks line up
+    check_snapshot("title", &["--snapshot-ticks", "105"]);
+}